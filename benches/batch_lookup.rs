@@ -0,0 +1,59 @@
+//! Compares `Mmdb::query_ip` in a loop against `Mmdb::query_ips`.
+//!
+//! Requires a database: `IPINFO_BENCH_DB=path/to/db.mmdb cargo bench --bench batch_lookup`
+
+use std::{fs::File, io::BufReader, net::IpAddr, time::Instant};
+
+const ADDRESS_COUNT: usize = 200_000;
+
+fn main() {
+    let Some(db_path) = std::env::var_os("IPINFO_BENCH_DB") else {
+        eprintln!("IPINFO_BENCH_DB is not set, skipping batch lookup benchmark");
+        return;
+    };
+
+    let file = File::open(db_path).expect("failed to open database");
+    let mut db = mmdb::Mmdb::new(BufReader::new(file)).expect("failed to read database");
+
+    // Log-like input: addresses clustered into a few thousand networks, with repeats
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let networks = (0..4096).map(|_| next() as u32).collect::<Vec<_>>();
+    let ips = (0..ADDRESS_COUNT)
+        .map(|_| {
+            let network = networks[next() as usize % networks.len()] & 0xffff_ff00;
+            IpAddr::from((network | (next() as u32 & 0xff)).to_be_bytes())
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let single = ips
+        .iter()
+        .map(|ip| db.query_ip(*ip).expect("single lookup failed"))
+        .collect::<Vec<_>>();
+    let single_time = start.elapsed();
+
+    let start = Instant::now();
+    let batch = db.query_ips(&ips).expect("batch lookup failed");
+    let batch_time = start.elapsed();
+
+    let mismatches = single
+        .iter()
+        .zip(&batch)
+        .filter(|(a, b)| format!("{a:?}") != format!("{b:?}"))
+        .count();
+    assert_eq!(mismatches, 0, "batch results differ from single lookups");
+
+    println!("{ADDRESS_COUNT} addresses");
+    println!("single lookups: {single_time:?}");
+    println!("batch lookup:   {batch_time:?}");
+    println!(
+        "speedup:        {:.2}x",
+        single_time.as_secs_f64() / batch_time.as_secs_f64()
+    );
+}
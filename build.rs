@@ -0,0 +1,22 @@
+use std::{env, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_DB").is_none() {
+        return;
+    }
+
+    println!("cargo:rerun-if-env-changed=IPINFO_EMBEDDED_DB");
+    let path = env::var("IPINFO_EMBEDDED_DB")
+        .expect("the embedded-db feature needs IPINFO_EMBEDDED_DB set to the path of a database");
+
+    // Relative paths are taken from the crate root rather than from the including source file
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let path = Path::new(&manifest_dir)
+        .join(path)
+        .canonicalize()
+        .expect("IPINFO_EMBEDDED_DB does not point to an existing file");
+
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rustc-env=IPINFO_EMBEDDED_DB_PATH={}", path.display());
+}
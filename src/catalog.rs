@@ -0,0 +1,179 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use ini::Ini;
+
+use crate::downloader::{self, Download, DownloadEvent, InstalledVersion, Verification};
+
+/// A database installed into the catalog under a name, as recorded in its manifest
+#[derive(Clone, Debug)]
+pub struct CatalogEntry {
+    pub name: String,
+    /// Where it was downloaded from
+    pub url: String,
+    /// SHA-256 of the installed database, hex encoded
+    pub sha256: String,
+    pub installed_at: SystemTime,
+}
+
+impl CatalogEntry {
+    pub fn path(&self) -> PathBuf {
+        database_path(&self.name)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatalogError {
+    #[error("Invalid database name '{0}', only letters, digits, '-', '_' and '.' are allowed")]
+    InvalidName(String),
+    #[error("No database named '{0}' is installed")]
+    NotInstalled(String),
+    #[error("IO error encountered while updating the catalog")]
+    BadIo(#[from] std::io::Error),
+}
+
+/// Where named databases are installed, `databases` in the data directory
+pub fn catalog_dir() -> PathBuf {
+    let mut catalog_dir = downloader::data_dir();
+    catalog_dir.push("databases");
+    let _ = std::fs::create_dir_all(&catalog_dir);
+    catalog_dir
+}
+
+fn manifest_path() -> PathBuf {
+    let mut manifest_path = catalog_dir();
+    manifest_path.push("manifest.ini");
+    manifest_path
+}
+
+/// Where the database called `name` is (or would be) installed
+pub fn database_path(name: &str) -> PathBuf {
+    let mut path = catalog_dir();
+    path.push(format!("{name}.mmdb"));
+    path
+}
+
+// Names end up in file names and manifest sections, so keep them to something safe for both
+fn check_name(name: &str) -> Result<(), CatalogError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(CatalogError::InvalidName(name.to_string()))
+    }
+}
+
+/// Every installed database, by name
+pub fn list() -> Vec<CatalogEntry> {
+    let Ok(manifest) = Ini::load_from_file(manifest_path()) else {
+        return vec![];
+    };
+    let mut entries = manifest
+        .iter()
+        .filter_map(|(name, section)| {
+            let installed_at = section.get("installed_at")?.parse().ok()?;
+            Some(CatalogEntry {
+                name: name?.to_string(),
+                url: section.get("url")?.to_string(),
+                sha256: section.get("sha256")?.to_string(),
+                installed_at: SystemTime::UNIX_EPOCH + Duration::from_secs(installed_at),
+            })
+        })
+        // Entries whose database was deleted by hand are as good as removed
+        .filter(|entry| entry.path().exists())
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+pub fn get(name: &str) -> Result<CatalogEntry, CatalogError> {
+    list()
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| CatalogError::NotInstalled(name.to_string()))
+}
+
+/// The catalog entry of the database at `path`, if it is one
+pub fn entry_for_path(path: &Path) -> Option<CatalogEntry> {
+    list().into_iter().find(|entry| entry.path() == path)
+}
+
+/// Downloads the database at `url` into the catalog as `name`, replacing any database already
+/// installed under that name once the new one has passed `verification` and validates.
+pub fn install(
+    name: String,
+    url: String,
+    verification: Verification,
+) -> Result<Download, CatalogError> {
+    check_name(&name)?;
+    Ok(downloader::spawn_download(move |tx, cancel| {
+        let path = database_path(&name);
+        downloader::download(&url, &path, &verification, None, tx, cancel)?;
+        let entry = CatalogEntry {
+            sha256: downloader::to_hex(&downloader::file_sha256(&path)?),
+            name,
+            url,
+            installed_at: SystemTime::now(),
+        };
+        add_to_manifest(&entry)?;
+        Ok(DownloadEvent::Done(path))
+    }))
+}
+
+/// Deletes the database called `name` and its manifest entry
+pub fn remove(name: &str) -> Result<(), CatalogError> {
+    check_name(name)?;
+    let path = database_path(name);
+    let mut manifest = Ini::load_from_file(manifest_path()).unwrap_or_default();
+    if manifest.delete(Some(name)).is_none() && !path.exists() {
+        return Err(CatalogError::NotInstalled(name.to_string()));
+    }
+    manifest.write_to_file(manifest_path())?;
+
+    for path in [path.clone(), path.with_extension("version")] {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    match std::fs::remove_dir_all(downloader::versions_dir(&path)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Updates the manifest entry of the database at `path` after an earlier version of it was
+/// put back, if it is in the catalog
+pub fn record_rollback(path: &Path, installed: Option<&InstalledVersion>) -> std::io::Result<()> {
+    let Some(mut entry) = entry_for_path(path) else {
+        return Ok(());
+    };
+    entry.sha256 = downloader::to_hex(&downloader::file_sha256(path)?);
+    if let Some(installed) = installed {
+        entry.url = installed.url.clone();
+        entry.installed_at = installed.installed_at;
+    }
+    add_to_manifest(&entry)
+}
+
+fn add_to_manifest(entry: &CatalogEntry) -> std::io::Result<()> {
+    let installed_at = entry
+        .installed_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // Read back right before writing, so installs running side by side don't lose each other
+    let mut manifest = Ini::load_from_file(manifest_path()).unwrap_or_default();
+    manifest
+        .with_section(Some(entry.name.as_str()))
+        .set("url", &entry.url)
+        .set("sha256", &entry.sha256)
+        .set("installed_at", installed_at.to_string());
+    manifest.write_to_file(manifest_path())
+}
@@ -0,0 +1,343 @@
+use std::{path::PathBuf, time::Duration};
+
+use ini::Ini;
+use mmdb::{
+    dns::ResolverConfig,
+    http::{self, HttpError, HttpSettings, Proxy},
+};
+
+use crate::downloader::{self, Verification, VerificationError};
+
+/// Settings shared by the CLI and the GUI, stored in `config.ini` next to the default database
+#[derive(Default)]
+pub struct Config {
+    /// The database the GUI opens on start
+    #[allow(dead_code)]
+    pub database_path: Option<String>,
+    /// Where the default database is downloaded and updated from, [`downloader::UPDATE_URL`]
+    /// for updates when unset
+    pub update_url: Option<String>,
+    /// Comma separated sources of the default database, tried in order after `update_url` fails
+    pub mirrors: Option<String>,
+    /// How many replaced versions of each database to keep for rollbacks
+    pub keep_versions: Option<String>,
+    /// Hex SHA-256 the default database has to have when it is downloaded
+    pub sha256: Option<String>,
+    /// Where to download a signature of the default database from, checked with `public_key`
+    pub signature_url: Option<String>,
+    /// Hex Ed25519 key that signatures of downloaded databases are checked with
+    pub public_key: Option<String>,
+    /// Proxy for downloads, in place of `HTTPS_PROXY`/`HTTP_PROXY`
+    pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    /// Comma separated hosts downloaded from without the proxy, added to `NO_PROXY`
+    pub no_proxy: Option<String>,
+    /// PEM file of extra root certificates to trust for downloads
+    pub ca_bundle: Option<String>,
+    /// Comma separated DNS servers, the system resolvers are used when unset
+    pub dns_servers: Option<String>,
+    /// Seconds to wait for each DNS server
+    pub dns_timeout: Option<String>,
+    pub dns_attempts: Option<String>,
+    /// Whether the CLI keeps DNS responses between runs, in [`dns_cache_path`]
+    pub dns_disk_cache: Option<String>,
+}
+
+const DEFAULT_KEEP_VERSIONS: usize = 3;
+// Longer DNS timeouts are surely mistakes, and far enough out they overflow deadlines
+const MAX_DNS_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// Environment variable with the sources of the default database, overriding the config file
+pub const DB_URL_VAR: &str = "IPINFO_DB_URL";
+
+pub fn config_path() -> PathBuf {
+    let mut config_path = downloader::default_mmdb_path();
+    config_path.set_file_name("config.ini");
+    config_path
+}
+
+// Only the CLI keeps DNS responses on disk
+#[allow(dead_code)]
+pub fn dns_cache_path() -> PathBuf {
+    let mut cache_path = downloader::default_mmdb_path();
+    cache_path.set_file_name("dns_cache.bin");
+    cache_path
+}
+
+pub fn load_config() -> Config {
+    let Ok(ini) = Ini::load_from_file(config_path()) else {
+        return Config::default();
+    };
+    let get = |section, key| {
+        ini.get_from(Some(section), key)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    Config {
+        database_path: get("database", "path"),
+        update_url: get("database", "update_url"),
+        mirrors: get("database", "mirrors"),
+        keep_versions: get("database", "keep_versions"),
+        sha256: get("database", "sha256"),
+        signature_url: get("database", "signature_url"),
+        public_key: get("download", "public_key"),
+        proxy: get("download", "proxy"),
+        proxy_username: get("download", "proxy_username"),
+        proxy_password: get("download", "proxy_password"),
+        no_proxy: get("download", "no_proxy"),
+        ca_bundle: get("download", "ca_bundle"),
+        dns_servers: get("dns", "servers"),
+        dns_timeout: get("dns", "timeout"),
+        dns_attempts: get("dns", "attempts"),
+        dns_disk_cache: get("dns", "disk_cache"),
+    }
+}
+
+// Only the GUI saves its settings
+#[allow(dead_code)]
+pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = config_path();
+    // Keep anything else the user put in the file
+    let mut ini = Ini::load_from_file(&config_path).unwrap_or_default();
+    set_or_delete(
+        &mut ini,
+        "database",
+        "path",
+        config.database_path.as_deref(),
+    );
+    set_or_delete(
+        &mut ini,
+        "database",
+        "update_url",
+        config.update_url.as_deref(),
+    );
+    set_or_delete(&mut ini, "database", "mirrors", config.mirrors.as_deref());
+    set_or_delete(
+        &mut ini,
+        "database",
+        "keep_versions",
+        config.keep_versions.as_deref(),
+    );
+    set_or_delete(&mut ini, "database", "sha256", config.sha256.as_deref());
+    set_or_delete(
+        &mut ini,
+        "database",
+        "signature_url",
+        config.signature_url.as_deref(),
+    );
+    set_or_delete(&mut ini, "download", "proxy", config.proxy.as_deref());
+    set_or_delete(
+        &mut ini,
+        "download",
+        "proxy_username",
+        config.proxy_username.as_deref(),
+    );
+    set_or_delete(
+        &mut ini,
+        "download",
+        "proxy_password",
+        config.proxy_password.as_deref(),
+    );
+    set_or_delete(&mut ini, "download", "no_proxy", config.no_proxy.as_deref());
+    set_or_delete(
+        &mut ini,
+        "download",
+        "ca_bundle",
+        config.ca_bundle.as_deref(),
+    );
+    set_or_delete(
+        &mut ini,
+        "download",
+        "public_key",
+        config.public_key.as_deref(),
+    );
+    set_or_delete(&mut ini, "dns", "servers", config.dns_servers.as_deref());
+    set_or_delete(&mut ini, "dns", "timeout", config.dns_timeout.as_deref());
+    set_or_delete(&mut ini, "dns", "attempts", config.dns_attempts.as_deref());
+    set_or_delete(
+        &mut ini,
+        "dns",
+        "disk_cache",
+        config.dns_disk_cache.as_deref(),
+    );
+
+    ini.write_to_file(&config_path)?;
+    Ok(())
+}
+
+// Only used by save_config
+#[allow(dead_code)]
+fn set_or_delete(ini: &mut Ini, section: &str, key: &str, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            ini.with_section(Some(section)).set(key, value);
+        }
+        None => {
+            ini.delete_from(Some(section), key);
+        }
+    }
+}
+
+impl Config {
+    /// Where the default database is downloaded from, in the order they are tried: the sources
+    /// in `IPINFO_DB_URL` if it is set, otherwise `update_url` (or `default`) and then `mirrors`
+    pub fn database_sources(&self, default: &str) -> Vec<String> {
+        if let Ok(sources) = std::env::var(DB_URL_VAR) {
+            let sources = downloader::parse_sources(&sources);
+            if !sources.is_empty() {
+                return sources;
+            }
+        }
+        let mut sources = vec![self.update_url.as_deref().unwrap_or(default).to_string()];
+        if let Some(mirrors) = &self.mirrors {
+            sources.extend(downloader::parse_sources(mirrors));
+        }
+        sources
+    }
+
+    /// The checks the default database has to pass when downloaded from `sources`: the
+    /// configured SHA-256 and signature on top of those built in for the first source
+    pub fn database_verification(
+        &self,
+        sources: &[String],
+    ) -> Result<Verification, VerificationError> {
+        Verification::with_user_checks(
+            sources.first().map_or("", String::as_str),
+            self.sha256.as_deref(),
+            self.signature_url.as_deref(),
+            self.public_key.as_deref(),
+        )
+    }
+
+    /// How downloads reach servers: the proxies from the environment unless one is configured,
+    /// with the configured credentials and CA bundle on top.
+    pub fn http_settings(&self) -> Result<HttpSettings, HttpError> {
+        let mut settings = HttpSettings::from_env()?;
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::parse(proxy)?;
+            settings.https_proxy = Some(proxy.clone());
+            settings.http_proxy = Some(proxy);
+        }
+        if let Some(username) = &self.proxy_username {
+            let password = self.proxy_password.clone().unwrap_or_default();
+            for proxy in [&mut settings.https_proxy, &mut settings.http_proxy]
+                .into_iter()
+                .flatten()
+            {
+                proxy.credentials = Some((username.clone(), password.clone()));
+            }
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            settings.no_proxy.extend(http::parse_no_proxy(no_proxy));
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            settings.add_ca_bundle(std::path::Path::new(ca_bundle))?;
+        }
+        Ok(settings)
+    }
+
+    pub fn keep_versions(&self) -> usize {
+        self.keep_versions
+            .as_deref()
+            .and_then(|keep| keep.parse().ok())
+            .unwrap_or(DEFAULT_KEEP_VERSIONS)
+    }
+
+    // Only the CLI keeps DNS responses on disk
+    #[allow(dead_code)]
+    pub fn dns_disk_cache_enabled(&self) -> bool {
+        matches!(
+            self.dns_disk_cache
+                .as_deref()
+                .map(str::to_ascii_lowercase)
+                .as_deref(),
+            Some("true" | "yes" | "1" | "on")
+        )
+    }
+
+    /// Builds the resolver configuration, using the system resolvers (and their options) for
+    /// anything that isn't set. The system resolvers are only looked for when no servers are
+    /// configured, so a machine without any doesn't warn about falling back for nothing.
+    pub fn resolver_config(&self) -> Result<ResolverConfig, String> {
+        let servers = match &self.dns_servers {
+            Some(servers) => mmdb::dns::config::parse_server_list(servers)
+                .map_err(|server| format!("invalid DNS server '{server}'"))?,
+            None => vec![],
+        };
+        let mut resolver_config = if servers.is_empty() {
+            ResolverConfig::system()
+        } else {
+            ResolverConfig::with_system_options(servers)
+        };
+        if let Some(timeout) = &self.dns_timeout {
+            resolver_config.timeout = timeout
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| *secs > 0.0)
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .filter(|timeout| *timeout <= MAX_DNS_TIMEOUT)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid DNS timeout '{timeout}', expected up to {} seconds",
+                        MAX_DNS_TIMEOUT.as_secs()
+                    )
+                })?;
+        }
+        if let Some(attempts) = &self.dns_attempts {
+            resolver_config.attempts = attempts
+                .parse::<u32>()
+                .ok()
+                .filter(|attempts| *attempts > 0)
+                .ok_or_else(|| format!("invalid DNS attempts '{attempts}'"))?;
+        }
+        Ok(resolver_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    #[test]
+    fn configured_dns_servers_replace_the_system_ones() {
+        let config = Config {
+            dns_servers: Some("192.0.2.53, 192.0.2.54:5353".to_string()),
+            ..Config::default()
+        };
+        let resolver_config = config.resolver_config().unwrap();
+        let servers: Vec<_> = resolver_config
+            .servers
+            .iter()
+            .map(|server| server.udp_address())
+            .collect();
+        assert_eq!(
+            servers,
+            [
+                Some(SocketAddr::from(([192, 0, 2, 53], 53))),
+                Some(SocketAddr::from(([192, 0, 2, 54], 5353))),
+            ]
+        );
+    }
+
+    #[test]
+    fn dns_timeout_is_at_most_an_hour() {
+        let resolver_config = |timeout: &str| {
+            Config {
+                dns_servers: Some("192.0.2.53".to_string()),
+                dns_timeout: Some(timeout.to_string()),
+                ..Config::default()
+            }
+            .resolver_config()
+        };
+        assert_eq!(
+            resolver_config("0.5").unwrap().timeout,
+            Duration::from_millis(500)
+        );
+        assert_eq!(resolver_config("3600").unwrap().timeout, MAX_DNS_TIMEOUT);
+        for timeout in ["3601", "1e19", "1e20", "inf", "NaN", "0", "-1"] {
+            assert!(resolver_config(timeout).is_err(), "{timeout}");
+        }
+    }
+}
@@ -1,58 +1,956 @@
-use std::{env, fs::File, io::BufWriter, path::PathBuf, thread::JoinHandle};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use byteorder::WriteBytesExt;
+use ed25519_dalek::{Signature, VerifyingKey};
+use ini::{Ini, ParseOption};
+use mmdb::http;
+use sha2::{Digest, Sha256};
+
+use crate::catalog;
 
 const URL: &str = "https://github.com/iplocate/ip-address-databases/raw/d2264aeeffceb0ec401a05581a9401150a79eb5a/ip-to-asn/ip-to-asn.mmdb?download=true";
+// SHA-256 of the file at `URL`, which is pinned to a commit so it never changes. Unset until
+// it has been taken from a trusted copy of the file, in which case only validation applies.
+const SHA256: Option<&str> = None;
+/// Where updates are fetched from unless configured otherwise, the latest version of the
+/// default database
+pub const UPDATE_URL: &str = "https://github.com/iplocate/ip-address-databases/raw/main/ip-to-asn/ip-to-asn.mmdb?download=true";
+
+// Written to disk, and checked for cancellation, this many bytes at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+// At most one progress event is sent per interval
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 #[allow(dead_code)]
 pub enum DownloadEvent {
-    Progress(f64),
+    Progress(DownloadProgress),
     Done(PathBuf),
+    /// The installed database is already the latest one
+    UpToDate(PathBuf),
+    /// Downloading from `url` failed, the next source is tried
+    SourceFailed {
+        url: String,
+        error: DownloadError,
+    },
+    /// Something went wrong that doesn't stop the database from being installed
+    Warning(String),
+    /// The download failed or was cancelled, and nothing was installed
+    Error(DownloadError),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DownloadProgress {
+    /// Bytes downloaded so far, including any resumed from an earlier attempt
+    pub received: u64,
+    /// The size of the whole file, if the server sent it
+    pub total: Option<u64>,
+    /// Bytes per second since this attempt started
+    pub rate: f64,
+}
+
+impl DownloadProgress {
+    /// How far along the download is, from 0 to 1 (0 while the size is unknown)
+    pub fn fraction(&self) -> f64 {
+        match self.total {
+            Some(total) if total > 0 => self.received as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let megabytes = |bytes: f64| bytes / 1_000_000.0;
+        write!(f, "{:.1}", megabytes(self.received as f64))?;
+        if let Some(total) = self.total {
+            write!(f, "/{:.1}", megabytes(total as f64))?;
+        }
+        write!(f, " MB, {:.1} MB/s", megabytes(self.rate))
+    }
+}
+
+/// Stops a download from another thread. It ends with [`DownloadError::Cancelled`], keeping
+/// what was downloaded so far to be resumed next time.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A download running on its own thread. Its last event is always `Done`, `UpToDate` or `Error`,
+/// after which the thread exits.
+pub struct Download {
+    pub events: Receiver<DownloadEvent>,
+    pub cancel: CancelHandle,
+}
+
+/// Runs `download` on a new thread, sending what it returns as the last event
+pub fn spawn_download<F>(download: F) -> Download
+where
+    F: FnOnce(&Sender<DownloadEvent>, &CancelHandle) -> Result<DownloadEvent, DownloadError>
+        + Send
+        + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel::<DownloadEvent>();
+    let cancel = CancelHandle::default();
+    let thread_cancel = cancel.clone();
+    std::thread::spawn(move || {
+        let event = download(&tx, &thread_cancel).unwrap_or_else(DownloadEvent::Error);
+        let _ = tx.send(event);
+    });
+
+    Download { events: rx, cancel }
 }
 pub fn default_mmdb_exists() -> bool {
     std::fs::exists(default_mmdb_path()).unwrap_or(false)
 }
 
-#[allow(dead_code)]
+/// Where the default database is first downloaded from unless configured otherwise
 pub fn download_url() -> &'static str {
     URL
 }
 
-pub fn download_default_mmdb() -> (std::sync::mpsc::Receiver<DownloadEvent>, JoinHandle<()>) {
-    let (tx, rx) = std::sync::mpsc::channel::<DownloadEvent>();
-    let handle = std::thread::spawn(move || {
-        let resp = tinyget::get(URL).send_lazy().unwrap();
+/// Splits a comma separated list of download sources
+pub fn parse_sources(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|source| !source.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Checks a downloaded file has to pass before it is installed, on the file as downloaded
+/// (before any archive is extracted)
+#[derive(Clone, Debug, Default)]
+pub struct Verification {
+    pub sha256: Option<[u8; 32]>,
+    pub signature: Option<SignatureCheck>,
+}
+
+/// A detached Ed25519 signature over the whole file, such as one made with
+/// `openssl pkeyutl -sign -rawin -inkey key.pem -in db.mmdb -out db.mmdb.sig`
+#[derive(Clone, Debug)]
+pub struct SignatureCheck {
+    /// Where the signature is downloaded from, as 64 raw bytes or hex
+    pub url: String,
+    pub public_key: [u8; 32],
+}
+
+impl Verification {
+    /// The checks built in for `url`, only the pinned default database has any
+    pub fn for_url(url: &str) -> Verification {
+        Verification {
+            // A typo in the digest must not quietly turn the check off
+            sha256: SHA256
+                .filter(|_| url == URL)
+                .map(|sha256| parse_hex(sha256).expect("SHA256 is not 64 hex digits")),
+            signature: None,
+        }
+    }
+
+    /// The checks built in for `url` with the user's on top: a hex `sha256` the file must have,
+    /// which replaces any built in, and a signature at `signature_url` made with the hex Ed25519
+    /// `public_key`.
+    pub fn with_user_checks(
+        url: &str,
+        sha256: Option<&str>,
+        signature_url: Option<&str>,
+        public_key: Option<&str>,
+    ) -> Result<Verification, VerificationError> {
+        let mut verification = Verification::for_url(url);
+        if let Some(sha256) = sha256 {
+            let sha256 =
+                parse_hex(sha256).ok_or_else(|| VerificationError::InvalidSha256(sha256.into()))?;
+            verification.sha256 = Some(sha256);
+        }
+        if let Some(signature_url) = signature_url {
+            let public_key = public_key.ok_or(VerificationError::MissingPublicKey)?;
+            let public_key = parse_hex(public_key)
+                .ok_or_else(|| VerificationError::InvalidPublicKey(public_key.into()))?;
+            verification.signature = Some(SignatureCheck {
+                url: signature_url.to_string(),
+                public_key,
+            });
+        }
+        Ok(verification)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    #[error("Invalid SHA-256 '{0}', expected 64 hex digits")]
+    InvalidSha256(String),
+    #[error("Invalid public key '{0}', expected the 64 hex digits of an Ed25519 key")]
+    InvalidPublicKey(String),
+    #[error("A signature URL was given without a public key to check it with")]
+    MissingPublicKey,
+}
+
+/// Where the installed default database came from, kept in `db.version` next to it so updates
+/// can ask the server whether it has changed since
+#[derive(Clone, Debug)]
+pub struct InstalledVersion {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When it was downloaded
+    pub installed_at: SystemTime,
+}
+
+impl InstalledVersion {
+    fn load(path: &Path) -> Option<InstalledVersion> {
+        let ini = load_version_file(path).ok()?;
+        let section = ini.section(Some("installed"))?;
+        let installed_at = section.get("installed_at")?.parse().ok()?;
+        Some(InstalledVersion {
+            url: section.get("url")?.to_string(),
+            etag: section.get("etag").map(str::to_string),
+            last_modified: section.get("last_modified").map(str::to_string),
+            installed_at: SystemTime::UNIX_EPOCH + Duration::from_secs(installed_at),
+        })
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let installed_at = self
+            .installed_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut ini = Ini::new();
+        let mut section = ini.with_section(Some("installed"));
+        section.set("url", &self.url);
+        section.set("installed_at", installed_at.to_string());
+        if let Some(etag) = &self.etag {
+            section.set("etag", etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            section.set("last_modified", last_modified);
+        }
+        ini.write_to_file(path)
+    }
+}
+
+/// The version of the default database, if it was installed by a download
+pub fn installed_version() -> Option<InstalledVersion> {
+    InstalledVersion::load(&version_path(&default_mmdb_path()))
+}
+
+/// Describes how old the database at `path` is, from when it was `built` and, for the default
+/// database or one in the catalog, when it was installed. One line for each that is known.
+pub fn describe_database_age(path: Option<&Path>, built: Option<SystemTime>) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(built) = built {
+        lines.push(format!("Built: {}", describe_age(built)));
+    }
+    let installed = match path {
+        Some(path) if path == default_mmdb_path() => {
+            installed_version().map(|installed| (installed.installed_at, installed.url))
+        }
+        Some(path) => catalog::entry_for_path(path).map(|entry| (entry.installed_at, entry.url)),
+        None => None,
+    };
+    if let Some((installed_at, url)) = installed {
+        lines.push(format!(
+            "Installed: {} from {url}",
+            describe_age(installed_at)
+        ));
+    }
+    lines
+}
+
+fn version_path(path: &Path) -> PathBuf {
+    path.with_extension("version")
+}
+
+fn load_version_file(path: &Path) -> Result<Ini, ini::Error> {
+    // ETags are quoted, and the quotes are part of the value
+    let options = ParseOption {
+        enabled_quote: false,
+        ..ParseOption::default()
+    };
+    Ini::load_from_file_opt(path, options)
+}
+
+/// A previous version of a database, kept when a download or rollback replaced it
+#[derive(Clone, Debug)]
+pub struct ArchivedVersion {
+    pub path: PathBuf,
+    /// When it was replaced
+    pub replaced_at: SystemTime,
+    /// When it was built, from its metadata
+    pub build_time: Option<SystemTime>,
+    /// Where it came from, if it was installed by a download
+    pub installed: Option<InstalledVersion>,
+}
+
+/// Where previous versions of the database at `path` are kept, eg. `versions/db` for the default
+/// database
+pub fn versions_dir(path: &Path) -> PathBuf {
+    let mut versions_dir = path.with_file_name("versions");
+    versions_dir.push(path.file_stem().unwrap_or_default());
+    versions_dir
+}
+
+/// The kept versions of the database at `path`, newest first
+pub fn list_versions(path: &Path) -> Vec<ArchivedVersion> {
+    let Ok(dir) = std::fs::read_dir(versions_dir(path)) else {
+        return vec![];
+    };
+    let mut versions = dir
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "mmdb" {
+                return None;
+            }
+            // Named after when they were replaced
+            let replaced_at = path.file_stem()?.to_str()?.parse().ok()?;
+            let sidecar = load_version_file(&version_path(&path)).ok();
+            let build_time = sidecar
+                .as_ref()
+                .and_then(|ini| ini.get_from(Some("archived"), "build_epoch")?.parse().ok())
+                .map(|epoch| SystemTime::UNIX_EPOCH + Duration::from_secs(epoch));
+            Some(ArchivedVersion {
+                installed: InstalledVersion::load(&version_path(&path)),
+                replaced_at: SystemTime::UNIX_EPOCH + Duration::from_secs(replaced_at),
+                build_time,
+                path,
+            })
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by_key(|version| std::cmp::Reverse(version.replaced_at));
+    versions
+}
+
+/// Puts a kept `version` of the database at `path` back in its place. The database it replaces
+/// is kept in turn, so a rollback can be undone.
+pub fn rollback(path: &Path, version: &ArchivedVersion) -> Result<(), DownloadError> {
+    mmdb::Mmdb::open(&version.path)?.validate()?;
+    archive_current(path)?;
+    std::fs::rename(&version.path, path)?;
+    match &version.installed {
+        Some(installed) => installed.save(&version_path(path))?,
+        None => remove_if_exists(&version_path(path))?,
+    }
+    remove_if_exists(&version_path(&version.path))?;
+    catalog::record_rollback(path, version.installed.as_ref())?;
+    prune_versions(path, keep_versions())?;
+    Ok(())
+}
+
+/// Describes the kept versions of the database at `path`, numbered as [`rollback`] callers take
+/// them (1 is the most recently replaced), after the one currently installed
+pub fn describe_versions(path: &Path, built: Option<SystemTime>) -> String {
+    let mut text = String::from("Current\n");
+    for line in describe_database_age(Some(path), built) {
+        text.push_str(&format!("  {line}\n"));
+    }
+    for (i, version) in list_versions(path).iter().enumerate() {
+        text.push_str(&format!("{}\n", i + 1));
+        if let Some(build_time) = version.build_time {
+            text.push_str(&format!("  Built: {}\n", describe_age(build_time)));
+        }
+        if let Some(installed) = &version.installed {
+            text.push_str(&format!(
+                "  Installed: {} from {}\n",
+                describe_age(installed.installed_at),
+                installed.url
+            ));
+        }
+        text.push_str(&format!(
+            "  Replaced: {}\n",
+            describe_age(version.replaced_at)
+        ));
+    }
+    text
+}
+
+fn keep_versions() -> usize {
+    crate::config::load_config().keep_versions()
+}
+
+// Keeps a copy of the database at `path` (and what is known about it) in its versions
+// directory. Linked rather than moved, so the database never goes missing while it is replaced.
+fn archive_current(path: &Path) -> std::io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let versions_dir = versions_dir(path);
+    std::fs::create_dir_all(&versions_dir)?;
+    let mut replaced_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let archive_path = loop {
+        let archive_path = versions_dir.join(format!("{replaced_at}.mmdb"));
+        if !archive_path.exists() {
+            break archive_path;
+        }
+        replaced_at += 1;
+    };
+    if std::fs::hard_link(path, &archive_path).is_err() {
+        std::fs::copy(path, &archive_path)?;
+    }
+
+    let build_time = mmdb::Mmdb::open(path)
+        .ok()
+        .and_then(|mmdb| mmdb.build_time());
+    let mut sidecar = load_version_file(&version_path(path)).unwrap_or_default();
+    if let Some(build_time) = build_time {
+        let build_epoch = build_time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        sidecar
+            .with_section(Some("archived"))
+            .set("build_epoch", build_epoch.to_string());
+    }
+    sidecar.write_to_file(version_path(&archive_path))
+}
+
+// Deletes all but the newest `keep` versions of the database at `path`
+fn prune_versions(path: &Path, keep: usize) -> std::io::Result<()> {
+    for version in list_versions(path).into_iter().skip(keep) {
+        remove_if_exists(&version.path)?;
+        remove_if_exists(&version_path(&version.path))?;
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Describes a point in the past as its date and how long ago it was, eg.
+/// `2025-01-16 (12 days ago)`
+pub fn describe_age(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let days = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs()
+        / (24 * 60 * 60);
+    let ago = match days {
+        0 => "today".to_string(),
+        1 => "1 day ago".to_string(),
+        days => format!("{days} days ago"),
+    };
+    format!("{} ({ago})", format_date(since_epoch.as_secs()))
+}
+
+// Civil date from days since the Unix epoch, as described in
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn format_date(epoch_secs: u64) -> String {
+    let days = (epoch_secs / (24 * 60 * 60)) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Parses a hex encoded SHA-256 checksum or Ed25519 public key
+pub fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.trim().as_bytes();
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("Request failed: {0}")]
+    Request(#[from] http::HttpError),
+    #[error("Server responded with {0} {1}")]
+    BadStatus(i32, String),
+    #[error("Connection closed after {0} of {1} bytes")]
+    Incomplete(u64, u64),
+    #[error("IO error encountered while saving database: {0}")]
+    BadIo(#[from] std::io::Error),
+    #[error("Downloaded database is invalid")]
+    InvalidDatabase(#[from] mmdb::MmdbError),
+    #[error("Checksum mismatch, expected SHA-256 {expected} but got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("Signature verification failed: {0}")]
+    BadSignature(&'static str),
+    #[error("Could not read {0:?}: {1}")]
+    BadSource(PathBuf, std::io::Error),
+    #[error("No download sources given")]
+    NoSources,
+    #[error("Download cancelled")]
+    Cancelled,
+}
+
+/// Downloads the default database from the first of `sources` that works and passes
+/// `verification`
+pub fn download_default_mmdb(sources: Vec<String>, verification: Verification) -> Download {
+    spawn_download(move |tx, cancel| {
         let path = default_mmdb_path();
+        download_from_sources(&sources, &path, &verification, None, tx, cancel)?;
+        Ok(DownloadEvent::Done(path))
+    })
+}
+
+/// Replaces the default database with the one from `sources` if it is newer than the installed
+/// one, going by the ETag and Last-Modified headers the server sent last time. The new one has
+/// to pass `verification` before it is installed.
+pub fn update_default_mmdb(sources: Vec<String>, verification: Verification) -> Download {
+    spawn_download(move |tx, cancel| {
+        let path = default_mmdb_path();
+        // Without the database itself, there is nothing to compare against
+        let installed = installed_version().filter(|_| path.exists());
+        if download_from_sources(
+            &sources,
+            &path,
+            &verification,
+            installed.as_ref(),
+            tx,
+            cancel,
+        )? {
+            Ok(DownloadEvent::Done(path))
+        } else {
+            Ok(DownloadEvent::UpToDate(path))
+        }
+    })
+}
 
-        // let mut pb = ProgressBar::default();
-        let mut writer = BufWriter::new(File::create(&path).unwrap());
-        let mut total_bytes = None;
+/// Tries each of `sources` in order until one of them installs the database at `path`, sending
+/// [`DownloadEvent::SourceFailed`] for each one that doesn't. They are taken to be mirrors of
+/// the first, so all have to pass the same `verification`.
+fn download_from_sources(
+    sources: &[String],
+    path: &Path,
+    verification: &Verification,
+    installed: Option<&InstalledVersion>,
+    tx: &Sender<DownloadEvent>,
+    cancel: &CancelHandle,
+) -> Result<bool, DownloadError> {
+    let Some((last, rest)) = sources.split_last() else {
+        return Err(DownloadError::NoSources);
+    };
+    for url in rest {
+        match download(url, path, verification, installed, tx, cancel) {
+            Err(DownloadError::Cancelled) => return Err(DownloadError::Cancelled),
+            Err(error) => {
+                let _ = tx.send(DownloadEvent::SourceFailed {
+                    url: url.clone(),
+                    error,
+                });
+            }
+            result => return result,
+        }
+    }
+    download(last, path, verification, installed, tx, cancel)
+}
 
-        for byte in resp {
-            let (byte, len) = byte.unwrap();
-            let total_bytes = match total_bytes {
-                None => {
-                    total_bytes = Some(len);
-                    len
-                }
-                Some(len) => len,
-            };
-
-            writer.write_u8(byte).unwrap();
-            tx.send(DownloadEvent::Progress(
-                (total_bytes as f64 - len as f64) / total_bytes as f64,
-            ))
-            .unwrap();
-            // pb.inc_and_set_remaining(len as u64);
-        }
-        tx.send(DownloadEvent::Done(path)).unwrap();
-        // pb.finish();
+/// The body of a database being downloaded, from a server or a `file://` path
+struct Source {
+    body: Box<dyn Read>,
+    /// Where in the database `body` starts, non-zero when resuming a partial download
+    offset: u64,
+    /// Length of `body`, if known
+    length: Option<u64>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Downloads a database to `path`, going through a `.part` file next to it that is only
+/// renamed into place once the download has completed, passed `verification` and the database
+/// validates. A `.part` file left behind by an interrupted download of the same URL is resumed
+/// with a range request, as long as the file it was downloading hasn't changed since.
+///
+/// If the database at `path` is `installed` from the same URL, the server is asked for it only
+/// if it changed since, and `Ok(false)` is returned if it didn't. `file://` URLs are read from
+/// disk, going by the file's modification time instead.
+pub fn download(
+    url: &str,
+    path: &Path,
+    verification: &Verification,
+    installed: Option<&InstalledVersion>,
+    tx: &Sender<DownloadEvent>,
+    cancel: &CancelHandle,
+) -> Result<bool, DownloadError> {
+    let settings = crate::config::load_config().http_settings()?;
+    // Fetched first, so a missing signature doesn't cost a whole download
+    let signature = match &verification.signature {
+        Some(check) => Some(download_signature(&check.url, &settings)?),
+        None => None,
+    };
+    if cancel.is_cancelled() {
+        return Err(DownloadError::Cancelled);
+    }
+
+    let part_path = partial_download_path(path);
+    let part_version_path = partial_version_path(path);
+    // Whatever another URL left behind is of some other file
+    let resume = InstalledVersion::load(&part_version_path)
+        .filter(|partial| partial.url == url)
+        .and_then(|partial| {
+            let from = std::fs::metadata(&part_path).ok()?.len();
+            (from > 0).then_some(Resume {
+                from,
+                version: partial,
+            })
+        });
+
+    let installed = installed.filter(|installed| installed.url == url);
+    let opened = match file_url_path(url) {
+        Some(source_path) => open_file(&source_path, resume.as_ref(), installed)?,
+        None => open_http(url, &settings, resume.as_ref(), installed)?,
+    };
+    let Some(source) = opened else {
+        return Ok(false);
+    };
+    let resume_from = source.offset;
+
+    let version = InstalledVersion {
+        url: url.to_string(),
+        etag: source.etag,
+        last_modified: source.last_modified,
+        installed_at: SystemTime::now(),
+    };
+    let mut file = if resume_from > 0 {
+        OpenOptions::new().append(true).open(&part_path)?
+    } else {
+        // Recorded before anything is written, so an interrupted download knows what it was of
+        version.save(&part_version_path)?;
+        File::create(&part_path)?
+    };
+    let content_length = source.length;
+    let total = content_length.map(|length| resume_from + length);
+    let started = Instant::now();
+    let mut last_progress: Option<Instant> = None;
+    let mut received = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut body = source.body;
+    let mut finished = false;
+
+    while !finished {
+        chunk.clear();
+        (&mut body)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        finished = chunk.len() < CHUNK_SIZE;
+        file.write_all(&chunk)?;
+        received += chunk.len() as u64;
+
+        if cancel.is_cancelled() {
+            return Err(DownloadError::Cancelled);
+        }
+        if finished || last_progress.is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL) {
+            last_progress = Some(Instant::now());
+            let _ = tx.send(DownloadEvent::Progress(DownloadProgress {
+                received: resume_from + received,
+                total,
+                rate: received as f64 / started.elapsed().as_secs_f64().max(0.001),
+            }));
+        }
+    }
+    file.flush()?;
+    drop(file);
+
+    // The body just ends if the connection drops, so compare against the promised length.
+    // What did arrive is kept to be resumed next time.
+    if let Some(content_length) = content_length
+        && received < content_length
+    {
+        return Err(DownloadError::Incomplete(
+            resume_from + received,
+            resume_from + content_length,
+        ));
+    }
+
+    let validated = verify(&part_path, verification, signature.as_ref()).and_then(|()| {
+        unpack_archive(&part_path)?;
+        mmdb::Mmdb::open(&part_path)?.validate()?;
+        Ok(())
     });
+    if let Err(err) = validated {
+        // Resuming a complete but broken download would never fix it
+        let _ = std::fs::remove_file(&part_path);
+        let _ = std::fs::remove_file(&part_version_path);
+        return Err(err);
+    }
+    // Only worth a warning, the database is replaced either way
+    if let Err(err) = archive_current(path) {
+        let _ = tx.send(DownloadEvent::Warning(format!(
+            "failed to keep previous database version: {err}"
+        )));
+    }
+    std::fs::rename(&part_path, path)?;
+    let _ = std::fs::remove_file(&part_version_path);
+    // Only worth a warning, the next update just won't be conditional
+    if let Err(err) = version.save(&version_path(path)) {
+        let _ = tx.send(DownloadEvent::Warning(format!(
+            "failed to record database version: {err}"
+        )));
+    }
+    if let Err(err) = prune_versions(path, keep_versions()) {
+        let _ = tx.send(DownloadEvent::Warning(format!(
+            "failed to delete old database versions: {err}"
+        )));
+    }
+    Ok(true)
+}
+
+/// Where an interrupted download left off, and the version of the file it was downloading
+struct Resume {
+    from: u64,
+    version: InstalledVersion,
+}
+
+/// Asks the server for the database at `url`, picking up where `resume` left off if the file
+/// hasn't changed since. `None` if it is the `installed` version and hasn't changed since.
+fn open_http(
+    url: &str,
+    settings: &http::HttpSettings,
+    resume: Option<&Resume>,
+    installed: Option<&InstalledVersion>,
+) -> Result<Option<Source>, DownloadError> {
+    // Without a validator for If-Range, there's no telling whether the rest is of the same file
+    let mut resume = resume.and_then(|resume| Some((resume.from, if_range(&resume.version)?)));
+    let resp = loop {
+        let mut headers = vec![];
+        if let Some((from, validator)) = &resume {
+            headers.push(("Range", format!("bytes={from}-")));
+            headers.push(("If-Range", validator.clone()));
+        } else if let Some(installed) = installed {
+            if let Some(etag) = &installed.etag {
+                headers.push(("If-None-Match", etag.clone()));
+            }
+            if let Some(last_modified) = &installed.last_modified {
+                headers.push(("If-Modified-Since", last_modified.clone()));
+            }
+        }
+        let resp = http::get(url, &headers, settings)?;
+        let resume_from = resume.as_ref().map(|(from, _)| *from);
+        match resp.status_code {
+            304 if resume.is_none() && installed.is_some() => return Ok(None),
+            206 if resume_from.is_some() && content_range_start(&resp) == resume_from => {
+                break resp;
+            }
+            // Either a fresh download, or the file changed since and this is all of it
+            200 => {
+                resume = None;
+                break resp;
+            }
+            // The partial file is already as long as the database, or doesn't line up with
+            // what the server has now, so start over
+            206 | 416 if resume.is_some() => resume = None,
+            status => return Err(DownloadError::BadStatus(status, resp.reason_phrase)),
+        }
+    };
+    Ok(Some(Source {
+        offset: resume.map_or(0, |(from, _)| from),
+        length: resp
+            .headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok()),
+        etag: resp.headers.get("etag").cloned(),
+        last_modified: resp.headers.get("last-modified").cloned(),
+        body: Box::new(resp),
+    }))
+}
+
+/// Opens the database at `path` on disk, such as an offline mirror, the same way as
+/// [`open_http`]. Its modification time stands in for Last-Modified.
+fn open_file(
+    path: &Path,
+    resume: Option<&Resume>,
+    installed: Option<&InstalledVersion>,
+) -> Result<Option<Source>, DownloadError> {
+    let bad_source = |err| DownloadError::BadSource(path.to_path_buf(), err);
+    let mut file = File::open(path).map_err(bad_source)?;
+    let metadata = file.metadata().map_err(bad_source)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs().to_string());
+    // Only if the file is unchanged, and still long enough to hold what was downloaded of it
+    let offset = resume
+        .filter(|resume| {
+            modified.is_some()
+                && resume.version.last_modified == modified
+                && resume.from <= metadata.len()
+        })
+        .map_or(0, |resume| resume.from);
+    if offset == 0
+        && let Some(installed) = installed
+        && installed.last_modified.is_some()
+        && installed.last_modified == modified
+    {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(offset)).map_err(bad_source)?;
+    Ok(Some(Source {
+        body: Box::new(file),
+        offset,
+        length: Some(metadata.len() - offset),
+        etag: None,
+        last_modified: modified,
+    }))
+}
 
-    (rx, handle)
+/// The path a `file://` URL points at, `None` for any other URL
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = http::percent_decode(path.strip_prefix("localhost").unwrap_or(path));
+    // `file:///C:/databases/db.mmdb`
+    if cfg!(target_os = "windows") && path.as_bytes().get(2) == Some(&b':') {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+fn download_signature(
+    url: &str,
+    settings: &http::HttpSettings,
+) -> Result<Signature, DownloadError> {
+    let body = match file_url_path(url) {
+        Some(path) => std::fs::read(&path).map_err(|err| DownloadError::BadSource(path, err))?,
+        None => {
+            let mut resp = http::get(url, &[], settings)?;
+            if resp.status_code != 200 {
+                return Err(DownloadError::BadStatus(
+                    resp.status_code,
+                    resp.reason_phrase,
+                ));
+            }
+            let mut body = vec![];
+            resp.read_to_end(&mut body)?;
+            body
+        }
+    };
+    let bytes = match <[u8; 64]>::try_from(body.as_slice()) {
+        Ok(bytes) => Some(bytes),
+        Err(_) => std::str::from_utf8(&body).ok().and_then(parse_hex),
+    };
+    bytes
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or(DownloadError::BadSignature("signature is not 64 bytes"))
+}
+
+fn verify(
+    path: &Path,
+    verification: &Verification,
+    signature: Option<&Signature>,
+) -> Result<(), DownloadError> {
+    if let Some(expected) = verification.sha256 {
+        let actual = file_sha256(path)?;
+        if actual != expected {
+            return Err(DownloadError::ChecksumMismatch {
+                expected: to_hex(&expected),
+                actual: to_hex(&actual),
+            });
+        }
+    }
+
+    if let (Some(check), Some(signature)) = (&verification.signature, signature) {
+        let public_key = VerifyingKey::from_bytes(&check.public_key)
+            .map_err(|_| DownloadError::BadSignature("invalid public key"))?;
+        let contents = std::fs::read(path)?;
+        public_key
+            .verify_strict(&contents, signature)
+            .map_err(|_| DownloadError::BadSignature("signature does not match the database"))?;
+    }
+    Ok(())
+}
+
+pub fn file_sha256(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+fn partial_download_path(path: &Path) -> PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+// What to send in If-Range: a strong ETag, or else the Last-Modified date, as weak ETags aren't
+// allowed there
+fn if_range(version: &InstalledVersion) -> Option<String> {
+    version
+        .etag
+        .clone()
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| version.last_modified.clone())
+}
+
+// Where a partial download records the URL and version of the file it is of, as a `.info` so
+// it can't be mistaken for the version file of a database with a dotted name
+fn partial_version_path(path: &Path) -> PathBuf {
+    let mut info_path = partial_download_path(path).into_os_string();
+    info_path.push(".info");
+    PathBuf::from(info_path)
+}
+
+// Where the body of a 206 response starts, from a `Content-Range: bytes 100-999/1000` header
+fn content_range_start(resp: &http::Response) -> Option<u64> {
+    let range = resp.headers.get("content-range")?.strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// Replaces a downloaded `.mmdb.gz` or `.tar.gz` with the database it contains
+fn unpack_archive(path: &Path) -> Result<(), mmdb::MmdbError> {
+    let mut header = vec![];
+    File::open(path)?.take(512).read_to_end(&mut header)?;
+    if mmdb::archive::is_archive(&header) {
+        let contents = mmdb::archive::read_database(path)?;
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
 }
 
 pub fn default_mmdb_path() -> PathBuf {
+    let mut base_dir = data_dir();
+    base_dir.push("db.mmdb");
+    base_dir
+}
+
+/// The `ipinfo` directory databases and settings are kept in, created if it doesn't exist yet
+pub fn data_dir() -> PathBuf {
     let mut base_dir = if cfg!(target_os = "windows") {
         // Windows: %APPDATA% (C:\Users\Name\AppData\Roaming)
         env::var_os("APPDATA").map(PathBuf::from)
@@ -79,7 +977,206 @@ pub fn default_mmdb_path() -> PathBuf {
     base_dir.push("ipinfo");
 
     let _ = std::fs::create_dir_all(&base_dir);
-
-    base_dir.push("db.mmdb");
     base_dir
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    /// The smallest database that validates: an IPv4 tree of one node with no data on either
+    /// side, the data section separator, and the metadata every database needs
+    fn fixture() -> Vec<u8> {
+        let mut database = vec![0, 0, 1, 0, 0, 1];
+        database.extend([0; 16]);
+        database.extend(b"\xab\xcd\xefMaxMind.com");
+        // A map of 3 entries, with a u32 node count and u16 record size and ip version
+        database.push(0xe3);
+        database.extend(b"\x4anode_count\xc1\x01");
+        database.extend(b"\x4brecord_size\xa1\x18");
+        database.extend(b"\x4aip_version\xa1\x04");
+        database
+    }
+
+    /// An HTTP server on 127.0.0.1 that answers each request with what `respond` makes of it,
+    /// reporting every request it gets on the returned channel
+    fn stand_in(
+        respond: impl Fn(&str) -> Vec<u8> + Send + 'static,
+    ) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/db.mmdb", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(&stream);
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                stream.write_all(&respond(&request)).unwrap();
+                if tx.send(request).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    fn response(status: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend(body);
+        response
+    }
+
+    /// An empty directory of its own for each test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ipinfo-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cancel_handle() -> CancelHandle {
+        CancelHandle(Arc::new(AtomicBool::new(false)))
+    }
+
+    #[test]
+    fn built_in_checksum_is_only_for_the_pinned_database() {
+        // Also catches a malformed digest, which panics
+        assert_eq!(
+            Verification::for_url(URL).sha256.is_some(),
+            SHA256.is_some()
+        );
+        assert!(Verification::for_url(UPDATE_URL).sha256.is_none());
+        assert!(
+            Verification::for_url("file:///mnt/mirror/ip-to-asn.mmdb")
+                .sha256
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs SHA256 taken from a trusted copy of the pinned database"]
+    fn pinned_database_with_another_body_is_rejected() {
+        let dir = temp_dir("pinned");
+        let path = dir.join("db.mmdb");
+        std::fs::write(&path, fixture()).unwrap();
+
+        let result = verify(&path, &Verification::for_url(URL), None);
+
+        assert!(
+            matches!(result, Err(DownloadError::ChecksumMismatch { .. })),
+            "{result:?}"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failing_source_falls_through_to_the_next() {
+        let dir = temp_dir("fall-through");
+        let path = dir.join("db.mmdb");
+        let (broken, _) = stand_in(|_| response("500 Internal Server Error", b""));
+        let (mirror, _) = stand_in(|_| response("200 OK", &fixture()));
+        let (tx, rx) = mpsc::channel();
+
+        let sources = [broken.clone(), mirror.clone()];
+        let result = download_from_sources(
+            &sources,
+            &path,
+            &Verification::default(),
+            None,
+            &tx,
+            &cancel_handle(),
+        );
+
+        assert!(result.unwrap());
+        let failed: Vec<_> = rx
+            .try_iter()
+            .filter_map(|event| match event {
+                DownloadEvent::SourceFailed { url, error } => Some((url, error)),
+                _ => None,
+            })
+            .collect();
+        assert!(
+            matches!(
+                failed.as_slice(),
+                [(url, DownloadError::BadStatus(500, _))] if *url == broken
+            ),
+            "{failed:?}"
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), fixture());
+        assert_eq!(
+            InstalledVersion::load(&version_path(&path)).unwrap().url,
+            mirror
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn file_source_installs() {
+        let dir = temp_dir("file-source");
+        let path = dir.join("db.mmdb");
+        let mirror = dir.join("mirror.mmdb");
+        std::fs::write(&mirror, fixture()).unwrap();
+        let (tx, _rx) = mpsc::channel();
+
+        let result = download_from_sources(
+            &[format!("file://{}", mirror.display())],
+            &path,
+            &Verification::default(),
+            None,
+            &tx,
+            &cancel_handle(),
+        );
+
+        assert!(result.unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), fixture());
+        assert!(!partial_download_path(&path).exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn not_modified_is_up_to_date() {
+        let dir = temp_dir("not-modified");
+        let path = dir.join("db.mmdb");
+        std::fs::write(&path, fixture()).unwrap();
+        let (server, requests) = stand_in(|_| response("304 Not Modified", b""));
+        let installed = InstalledVersion {
+            url: server.clone(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            installed_at: SystemTime::now(),
+        };
+        let (tx, _rx) = mpsc::channel();
+
+        let result = download_from_sources(
+            &[server],
+            &path,
+            &Verification::default(),
+            Some(&installed),
+            &tx,
+            &cancel_handle(),
+        );
+
+        assert!(!result.unwrap());
+        let request = requests.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(
+            request
+                .to_ascii_lowercase()
+                .contains("if-none-match: \"v1\"\r\n"),
+            "{request}"
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), fixture());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
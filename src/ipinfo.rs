@@ -1,57 +1,399 @@
 use std::{
-    io::{BufReader, Write},
-    net::IpAddr,
+    io::Write,
     str::FromStr,
+    sync::{Mutex, Once},
 };
 
+mod catalog;
+mod config;
 mod downloader;
-mod mmdb;
+
+/// How long after installing the default database to suggest updating it
+const STALE_AFTER: std::time::Duration = std::time::Duration::from_secs(30 * 24 * 60 * 60);
 
 fn main() {
     let mut verbose = false;
+    let mut reverse = false;
+    let mut records = false;
+    let mut dns_disk_cache = false;
+    let mut update = false;
+    let mut list = false;
+    let mut versions = false;
+    let mut rollback: Option<String> = None;
+    let mut database_name: Option<String> = None;
+    let mut install: Option<(String, String)> = None;
+    let mut remove: Option<String> = None;
     let mut args = std::env::args();
     let program = args.next().unwrap();
     let program = std::path::Path::new(&program);
     let program = program.file_name().unwrap().to_string_lossy();
 
-    let args = args
-        .filter(|x| {
-            if x == "-v" || x == "--verbose" {
-                verbose = true;
-                false
-            } else {
-                true
-            }
-        })
-        .collect::<Vec<_>>();
-    if args.len() != 1 && args.len() != 2 {
+    let mut dns_servers: Option<String> = None;
+    let mut dns_timeout: Option<String> = None;
+    let mut dns_attempts: Option<String> = None;
+    let mut sources: Option<String> = None;
+    let mut sha256: Option<String> = None;
+    let mut signature_url: Option<String> = None;
+    let mut public_key: Option<String> = None;
+    let mut positional = vec![];
+    let mut missing_value = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            "-r" | "--reverse" => reverse = true,
+            "--records" => records = true,
+            "--dns-cache" => dns_disk_cache = true,
+            "--update" => update = true,
+            "--list" => list = true,
+            "--versions" => versions = true,
+            "--rollback" => match args.next() {
+                Some(version) => rollback = Some(version),
+                None => missing_value = true,
+            },
+            "--db" => match args.next() {
+                Some(name) => database_name = Some(name),
+                None => missing_value = true,
+            },
+            "--install" => match (args.next(), args.next()) {
+                (Some(name), Some(url)) => install = Some((name, url)),
+                _ => missing_value = true,
+            },
+            "--remove" => match args.next() {
+                Some(name) => remove = Some(name),
+                None => missing_value = true,
+            },
+            "-d" | "--dns-server" => match args.next() {
+                Some(server) => {
+                    let servers = dns_servers.get_or_insert_default();
+                    if !servers.is_empty() {
+                        servers.push(',');
+                    }
+                    servers.push_str(&server);
+                }
+                None => missing_value = true,
+            },
+            "--source" => match args.next() {
+                Some(source) => {
+                    let sources = sources.get_or_insert_default();
+                    if !sources.is_empty() {
+                        sources.push(',');
+                    }
+                    sources.push_str(&source);
+                }
+                None => missing_value = true,
+            },
+            "--sha256" => match args.next() {
+                Some(hex) => sha256 = Some(hex),
+                None => missing_value = true,
+            },
+            "--signature-url" => match args.next() {
+                Some(url) => signature_url = Some(url),
+                None => missing_value = true,
+            },
+            "--public-key" => match args.next() {
+                Some(hex) => public_key = Some(hex),
+                None => missing_value = true,
+            },
+            "--dns-timeout" => match args.next() {
+                Some(secs) => dns_timeout = Some(secs),
+                None => missing_value = true,
+            },
+            "--dns-attempts" => match args.next() {
+                Some(attempts) => dns_attempts = Some(attempts),
+                None => missing_value = true,
+            },
+            _ => positional.push(arg),
+        }
+    }
+    let args = positional;
+    let managing =
+        update || list || versions || rollback.is_some() || install.is_some() || remove.is_some();
+    // Managing databases doesn't need an ip address, and --db takes the place of `mmdb_path`
+    let min_args = if managing { 0 } else { 1 };
+    let max_args = if database_name.is_some() { 1 } else { 2 };
+    if missing_value || args.len() < min_args || args.len() > max_args {
         eprintln!("{program} -- locally query ip information via a MMDB database");
         eprintln!("USAGE: {program} <ip address> (mmdb_path)");
         eprintln!("   eg. {program} 1.1.1.1");
         eprintln!("   eg. {program} 1.1.1.1 ./ip_to_country.mmdb");
+        eprintln!("   eg. {program} 1.1.1.1 ./GeoLite2-ASN.tar.gz");
+        eprintln!("   eg. {program} --update");
+        eprintln!("   eg. {program} --update --source file:///mnt/mirror/ip-to-asn.mmdb");
+        eprintln!("   eg. {program} --install country https://example.com/ip-to-country.mmdb");
+        eprintln!("   eg. {program} 1.1.1.1 --db country");
         eprintln!("FLAGS:");
         eprintln!("       --verbose (-v)      Enables verbose logging");
+        eprintln!("       --reverse (-r)      Also looks up the hostname of the ip address");
+        eprintln!(
+            "       --records           Looks up the domain's MX, NS, TXT and SOA records instead, with info on the addresses of its mail and name servers"
+        );
+        eprintln!(
+            "       --dns-server (-d)   DNS server to resolve domains with, may be repeated or comma separated"
+        );
+        eprintln!(
+            "                           (default: [dns] servers in {:?}, then /etc/resolv.conf)",
+            config::config_path()
+        );
+        eprintln!(
+            "                           DNS over HTTPS and TLS servers are given as https://host/dns-query or tls://ip[:port][#name]"
+        );
+        eprintln!(
+            "       --dns-timeout       Seconds to wait for each DNS server before trying the next one"
+        );
+        eprintln!(
+            "       --dns-attempts      Number of times to go through the DNS servers before giving up"
+        );
+        eprintln!(
+            "       --dns-cache         Keeps DNS responses between runs, for as long as their TTLs allow"
+        );
+        eprintln!(
+            "       --update            Replaces the default database with the latest version, if it has changed since it was installed"
+        );
+        eprintln!(
+            "       --source <url>      Where to download the default database from, may be repeated or comma separated to try mirrors in order"
+        );
+        eprintln!(
+            "                           (default: ${}, or [database] update_url and mirrors in {:?}, or IPLocate.io)",
+            config::DB_URL_VAR,
+            config::config_path()
+        );
+        eprintln!(
+            "                           file:// URLs are read from disk, such as an offline mirror"
+        );
+        eprintln!(
+            "       --sha256 <hex>      SHA-256 the database being downloaded or installed has to have"
+        );
+        eprintln!(
+            "       --signature-url <url>  Where to download an Ed25519 signature of the database being downloaded or installed from"
+        );
+        eprintln!("       --public-key <hex>  Ed25519 key the signature has to be made with");
+        eprintln!(
+            "                           (default: [database] sha256 and signature_url, and [download] public_key in {:?})",
+            config::config_path()
+        );
+        eprintln!(
+            "       --db <name>         Queries the database installed in the catalog under <name>, instead of `mmdb_path`"
+        );
+        eprintln!(
+            "       --install <name> <url>  Downloads a database into the catalog under <name>, replacing any with that name"
+        );
+        eprintln!("       --list              Lists the databases installed in the catalog");
+        eprintln!("       --remove <name>     Deletes a database from the catalog");
+        eprintln!(
+            "       --versions          Lists the kept previous versions of the default database, or the one given with --db"
+        );
+        eprintln!(
+            "       --rollback <n>      Puts back version <n> from --versions, keeping the replaced one"
+        );
+        eprintln!(
+            "                           (the number kept is [database] keep_versions in {:?}, default: 3)",
+            config::config_path()
+        );
         eprintln!(
             "NOTE: the `mmdb_path` argument is optional, if not present, {program} can automatically download and use a default ip-to-asn mmdb database (provided by IPLocate.io)."
         );
+        if mmdb::embedded::is_available() {
+            eprintln!(
+                "      This build also contains an embedded database, which is used when no default database has been downloaded."
+            );
+        }
+        return;
+    }
+
+    // Flags take precedence over the environment and the config file
+    let database_sources = |default: &str| match &sources {
+        Some(sources) => downloader::parse_sources(sources),
+        None => config::load_config().database_sources(default),
+    };
+    let database_verification = |sources: &[String]| {
+        let mut config = config::load_config();
+        config.sha256 = sha256.clone().or(config.sha256);
+        config.signature_url = signature_url.clone().or(config.signature_url);
+        config.public_key = public_key.clone().or(config.public_key);
+        config.database_verification(sources)
+    };
+
+    if update {
+        let sources = database_sources(downloader::UPDATE_URL);
+        eprintln!(
+            "Checking '{}' for a newer database...",
+            sources.join("', '")
+        );
+        let verification = match database_verification(&sources) {
+            Ok(verification) => verification,
+            Err(err) => {
+                eprintln!("ERR: {err}");
+                return;
+            }
+        };
+        match wait_for_download(downloader::update_default_mmdb(sources, verification)) {
+            Ok(downloader::DownloadEvent::UpToDate(path)) => {
+                eprintln!("Database at {path:?} is already up to date");
+            }
+            Ok(_) => eprintln!("Updated database at {:?}", downloader::default_mmdb_path()),
+            Err(err) => {
+                eprintln!("ERR: failed to update database: {err}");
+                return;
+            }
+        }
+        for line in downloader::describe_database_age(Some(&downloader::default_mmdb_path()), None)
+        {
+            eprintln!("{line}");
+        }
+    }
+
+    if let Some((name, url)) = install {
+        // The configured SHA-256 and signature are the default database's, only the key applies
+        let verification = match downloader::Verification::with_user_checks(
+            &url,
+            sha256.as_deref(),
+            signature_url.as_deref(),
+            public_key
+                .clone()
+                .or(config::load_config().public_key)
+                .as_deref(),
+        ) {
+            Ok(verification) => verification,
+            Err(err) => {
+                eprintln!("ERR: {err}");
+                return;
+            }
+        };
+        let download = match catalog::install(name.clone(), url, verification) {
+            Ok(download) => download,
+            Err(err) => {
+                eprintln!("ERR: {err}");
+                return;
+            }
+        };
+        match wait_for_download(download) {
+            Ok(_) => eprintln!("Installed '{name}' at {:?}", catalog::database_path(&name)),
+            Err(err) => {
+                eprintln!("ERR: failed to install database '{name}': {err}");
+                return;
+            }
+        }
+    }
+
+    if let Some(name) = remove {
+        match catalog::remove(&name) {
+            Ok(()) => eprintln!("Removed '{name}'"),
+            Err(err) => {
+                eprintln!("ERR: {err}");
+                return;
+            }
+        }
+    }
+
+    if list {
+        let entries = catalog::list();
+        if entries.is_empty() {
+            eprintln!("No databases installed in {:?}", catalog::catalog_dir());
+        }
+        for entry in entries {
+            println!("{}", entry.name);
+            println!("  Source: {}", entry.url);
+            println!("  SHA-256: {}", entry.sha256);
+            println!(
+                "  Installed: {}",
+                downloader::describe_age(entry.installed_at)
+            );
+        }
+    }
+
+    if versions || rollback.is_some() {
+        let path = match &database_name {
+            Some(name) => match catalog::get(name) {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    eprintln!("ERR: {err}, see --list");
+                    return;
+                }
+            },
+            None => downloader::default_mmdb_path(),
+        };
+
+        if let Some(number) = rollback {
+            let kept = downloader::list_versions(&path);
+            let Some(version) = number
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| kept.get(number.checked_sub(1)?))
+            else {
+                eprintln!("ERR: no version '{number}' of {path:?} is kept, see --versions");
+                return;
+            };
+            match downloader::rollback(&path, version) {
+                Ok(()) => eprintln!("Rolled back {path:?} to version {number}"),
+                Err(err) => {
+                    eprintln!("ERR: failed to roll back: {err}");
+                    return;
+                }
+            }
+        }
+
+        if versions {
+            let built = mmdb::Mmdb::open(&path)
+                .ok()
+                .and_then(|mmdb| mmdb.build_time());
+            print!("{}", downloader::describe_versions(&path, built));
+        }
+    }
+
+    if managing && args.is_empty() {
         return;
     }
 
     let ip = args[0].clone();
+    if records && ip.parse::<std::net::IpAddr>().is_ok() {
+        eprintln!("ERR: --records needs a domain, not an ip address");
+        return;
+    }
 
-    let db_path = match args.get(1) {
-        Some(arg) => std::path::PathBuf::from_str(arg).expect("invalid path"),
-        None => {
+    let db_path = match (&database_name, args.get(1)) {
+        (Some(name), _) => match catalog::get(name) {
+            Ok(entry) => Some(entry.path()),
+            Err(err) => {
+                eprintln!("ERR: {err}, see --list");
+                return;
+            }
+        },
+        (None, Some(arg)) => Some(std::path::PathBuf::from_str(arg).expect("invalid path")),
+        (None, None) => {
             let db_path = downloader::default_mmdb_path();
 
-            if !downloader::default_mmdb_exists() {
+            if downloader::default_mmdb_exists() {
+                if let Some(installed) = downloader::installed_version()
+                    && installed
+                        .installed_at
+                        .elapsed()
+                        .is_ok_and(|age| age > STALE_AFTER)
+                {
+                    eprintln!(
+                        "NOTE: the database was installed {}, run `{program} --update` to check for a newer one",
+                        downloader::describe_age(installed.installed_at)
+                    );
+                }
+                Some(db_path)
+            } else if mmdb::embedded::is_available() {
+                // Air-gapped builds carry their own database, no need to download one
+                None
+            } else {
                 eprintln!(
                     "ERR: ip address database does not exist (searching at {:?})",
                     db_path
                 );
+                let sources = database_sources(downloader::download_url());
+                let verification = match database_verification(&sources) {
+                    Ok(verification) => verification,
+                    Err(err) => {
+                        eprintln!("ERR: {err}");
+                        return;
+                    }
+                };
                 eprint!(
                     "Automatically download database from '{}' (72.2MB)? y/n: ",
-                    downloader::download_url()
+                    sources.join("', '")
                 );
                 let mut line = String::new();
                 std::io::stdin().read_line(&mut line).unwrap();
@@ -61,54 +403,135 @@ fn main() {
                     return;
                 }
 
-                let mut pb = ProgressBar::default();
-
-                let (rx, handle) = downloader::download_default_mmdb();
-                for event in rx.iter() {
-                    match event {
-                        downloader::DownloadEvent::Progress(percent) => {
-                            // println!("{percent}");
-                            pb.set_progress(percent);
-                        }
-                        downloader::DownloadEvent::Done(_) => {
-                            pb.finish();
-                        }
-                    }
+                if let Err(err) =
+                    wait_for_download(downloader::download_default_mmdb(sources, verification))
+                {
+                    eprintln!("ERR: failed to download database: {err}");
+                    return;
                 }
-                drop(handle);
+                Some(db_path)
             }
-            db_path
         }
     };
 
-    let file = std::fs::File::open(db_path).unwrap();
-
     mmdb::set_verbose(verbose);
 
-    let mut mmdb = mmdb::Mmdb::new(BufReader::new(file)).unwrap();
+    // Flags take precedence over the config file
+    let mut config = config::load_config();
+    config.dns_servers = dns_servers.or(config.dns_servers);
+    config.dns_timeout = dns_timeout.or(config.dns_timeout);
+    config.dns_attempts = dns_attempts.or(config.dns_attempts);
+    let mut resolver_config = match config.resolver_config() {
+        Ok(resolver_config) => resolver_config,
+        Err(err) => {
+            eprintln!("ERR: {err}");
+            return;
+        }
+    };
 
-    let info = mmdb.query_string(&ip).unwrap();
+    let dns_disk_cache = dns_disk_cache || config.dns_disk_cache_enabled();
+    if dns_disk_cache {
+        // A missing or unreadable cache just means starting from an empty one
+        let cache = mmdb::dns::cache::DnsCache::load(config::dns_cache_path()).unwrap_or_default();
+        resolver_config.cache = Some(std::sync::Arc::new(cache));
+    }
+    let dns_cache = resolver_config.cache.clone();
 
-    if let Some(dns_info) = &info.dns_info {
-        println!(
-            "DNS: Resolved domain '{}' -> {}",
-            dns_info.domain, dns_info.resolved_ip
-        );
+    let mut mmdb = match &db_path {
+        Some(db_path) => mmdb::Mmdb::open(db_path).unwrap(),
+        None => mmdb::Mmdb::embedded().unwrap(),
+    };
+    if verbose {
+        for line in downloader::describe_database_age(db_path.as_deref(), mmdb.build_time()) {
+            println!("{line}");
+        }
     }
+    mmdb.set_resolver_config(resolver_config);
 
-    match info.data {
-        Some(typ) => {
-            println!("{typ}");
+    let result = if records {
+        mmdb.query_domain_records(&ip).map(|info| info.to_string())
+    } else {
+        let mut result = mmdb.query_string(&ip);
+        if reverse
+            && let Ok(info) = &mut result
+            && let Ok(ip) = ip.parse()
+        {
+            info.reverse_name = mmdb.reverse_lookup(ip).unwrap_or_else(|err| {
+                eprintln!("WARN: reverse DNS lookup failed: {err:?}");
+                None
+            });
         }
-        None => {
-            println!("No data found");
+        result.map(|info| info.to_string())
+    };
+
+    // Saved before reporting errors, so negative answers are remembered too
+    if dns_disk_cache
+        && let Some(cache) = dns_cache
+        && let Err(err) = cache.save(config::dns_cache_path())
+    {
+        eprintln!("WARN: failed to save DNS cache: {err:?}");
+    }
+
+    let info = result.unwrap();
+    println!("{info}");
+}
+
+// The download Ctrl-C cancels, if one is running
+static CURRENT_DOWNLOAD: Mutex<Option<downloader::CancelHandle>> = Mutex::new(None);
+
+/// Shows a progress bar for `download` until it ends, with Ctrl-C cancelling it (pressed again,
+/// it exits straight away). Returns the event it ended with, unless it failed.
+fn wait_for_download(
+    download: downloader::Download,
+) -> Result<downloader::DownloadEvent, downloader::DownloadError> {
+    static HANDLER: Once = Once::new();
+    HANDLER.call_once(|| {
+        let _ = ctrlc::set_handler(|| match CURRENT_DOWNLOAD.lock().unwrap().as_ref() {
+            Some(cancel) if !cancel.is_cancelled() => {
+                eprintln!("\nCancelling, press Ctrl-C again to exit now...");
+                cancel.cancel();
+            }
+            _ => std::process::exit(130),
+        });
+    });
+    *CURRENT_DOWNLOAD.lock().unwrap() = Some(download.cancel.clone());
+
+    let mut pb = ProgressBar::default();
+    let mut result = Err(downloader::DownloadError::Cancelled);
+    for event in download.events.iter() {
+        match event {
+            downloader::DownloadEvent::Progress(progress) => {
+                pb.set_progress(progress);
+            }
+            downloader::DownloadEvent::SourceFailed { url, error } => {
+                pb.finish();
+                pb = ProgressBar::default();
+                eprintln!("WARN: downloading from '{url}' failed: {error}, trying the next source");
+            }
+            downloader::DownloadEvent::Warning(warning) => {
+                pb.finish();
+                pb = ProgressBar::default();
+                eprintln!("WARN: {warning}");
+            }
+            downloader::DownloadEvent::Error(err) => {
+                pb.finish();
+                result = Err(err);
+            }
+            event => {
+                pb.finish();
+                result = Ok(event);
+            }
         }
     }
+    *CURRENT_DOWNLOAD.lock().unwrap() = None;
+    result
 }
 
 pub struct ProgressBar {
     percent: u8,
     width: u16,
+    details: String,
+    drawn: bool,
 }
 
 impl Default for ProgressBar {
@@ -116,35 +539,45 @@ impl Default for ProgressBar {
         Self {
             width: 50,
             percent: 0,
+            details: String::new(),
+            drawn: false,
         }
     }
 }
 
 impl ProgressBar {
-    pub fn set_progress(&mut self, progress: f64) {
-        let new_progress = (progress * 100.0) as u8;
-        if new_progress != self.percent {
-            self.percent = (progress * 100.0) as u8;
-            self.draw();
-        }
+    pub fn set_progress(&mut self, progress: downloader::DownloadProgress) {
+        // Events are already throttled, so every one is worth drawing
+        self.percent = (progress.fraction() * 100.0) as u8;
+        self.details = progress.to_string();
+        self.draw();
     }
 
     pub fn finish(&self) {
-        eprint!("\r{}        ", " ".repeat(self.width as usize));
+        if !self.drawn {
+            return;
+        }
+        eprint!(
+            "\r{}        {}",
+            " ".repeat(self.width as usize),
+            " ".repeat(self.details.len())
+        );
         let _ = std::io::stderr().lock().flush();
         eprintln!();
     }
 
-    fn draw(&self) {
+    fn draw(&mut self) {
+        self.drawn = true;
         let pct = self.percent as f64 / 100.0;
         let filled = (pct * self.width as f64) as u16;
         let empty = self.width - filled;
 
         eprint!(
-            "\r[{}>{}] {:.0}% ",
+            "\r[{}>{}] {:.0}% {} ",
             "=".repeat(filled as usize),
             " ".repeat(empty as usize),
             pct * 100.0,
+            self.details,
         );
         let _ = std::io::stderr().lock().flush();
     }
@@ -1,12 +1,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use std::{
     cell::RefCell,
-    fs::File,
-    io::BufReader,
     net::IpAddr,
     rc::Rc,
-    sync::mpsc::{Receiver, TryRecvError},
-    time::Instant,
+    sync::{
+        Arc,
+        mpsc::{Receiver, TryRecvError},
+    },
+    time::{Duration, Instant},
 };
 
 use fltk::{
@@ -15,9 +16,11 @@ use fltk::{
     dialog::NativeFileChooser,
     enums::CallbackTrigger,
     group::Flex,
-    input,
+    input, menu,
     misc::Progress,
-    prelude::{ButtonExt, DisplayExt, GroupExt, InputExt, WidgetBase, WidgetExt, WindowExt},
+    prelude::{
+        ButtonExt, DisplayExt, GroupExt, InputExt, MenuExt, WidgetBase, WidgetExt, WindowExt,
+    },
     text,
     window::Window,
 };
@@ -26,34 +29,53 @@ use fltk_theme::{ColorTheme, color_themes};
 #[cfg(target_os = "windows")]
 use windows_sys::Win32::Graphics::Dwm::DwmSetWindowAttribute;
 
+mod catalog;
+mod config;
 mod downloader;
-mod mmdb;
-
-use ini::Ini;
 
 enum Message {
     SendQuery(String),
     ShowMetadata,
     SaveConfig,
     ReloadConfig,
+    ReverseResolved {
+        query: String,
+        info: mmdb::MmdbInfo,
+        error: Option<String>,
+    },
+    RecordsResolved {
+        query: String,
+        text: String,
+    },
 }
 
 fn show_progress_modal<T: 'static, F, C>(
     title: &str,
     rx: Receiver<T>,
+    cancel: Option<downloader::CancelHandle>,
     to_progress: F,
     on_complete: C,
 ) where
     F: Fn(&T) -> Option<(f64, String)> + 'static,
     C: FnOnce(T) + 'static,
 {
-    let mut diag_win = Window::default().with_size(300, 60).with_label(title);
+    let height = if cancel.is_some() { 90 } else { 60 };
+    let mut diag_win = Window::default().with_size(300, height).with_label(title);
     diag_win.make_modal(true);
 
     let mut progress = Progress::new(0, 0, 300, 60, "");
     progress.set_minimum(0.0);
     progress.set_maximum(100.0);
     progress.set_selection_color(fltk::enums::Color::Blue);
+    if let Some(cancel) = cancel {
+        let mut cancel_btn = button::Button::new(100, 60, 100, 30, "Cancel");
+        cancel_btn.set_callback(move |btn| {
+            // The modal closes once the download has stopped
+            cancel.cancel();
+            btn.set_label("Cancelling...");
+            btn.deactivate();
+        });
+    }
     diag_win.end();
     diag_win.show();
 
@@ -62,9 +84,10 @@ fn show_progress_modal<T: 'static, F, C>(
     app::add_idle3(move |handle| match rx.try_recv() {
         Ok(event) => match to_progress(&event) {
             Some((p, label)) => {
-                if progress.value() as u64 != (p * 100.0) as u64 {
+                let label = format!("{label} ({:.0}%)", p * 100.0);
+                if progress.value() as u64 != (p * 100.0) as u64 || progress.label() != label {
                     progress.set_value(p * 100.0);
-                    progress.set_label(&format!("{label} ({:.0}%)", p * 100.0));
+                    progress.set_label(&label);
                 }
             }
             None => {
@@ -83,37 +106,51 @@ fn show_progress_modal<T: 'static, F, C>(
     });
 }
 
-fn get_config_path() -> std::path::PathBuf {
-    let mut config_path = downloader::default_mmdb_path();
-    config_path.set_file_name("config.ini");
-    config_path
-}
-
-fn load_config() -> Option<String> {
-    let config_path = get_config_path();
-    if config_path.exists() {
-        match Ini::load_from_file(&config_path) {
-            Ok(ini) => {
-                if let Some(section) = ini.section(Some("database")) {
-                    section.get("path").map(|s| s.to_string())
-                } else {
-                    None
-                }
-            }
-            Err(_) => None,
+/// Lists the catalog in `choice`, selecting the database at `loaded_path` if it is one of them
+fn fill_catalog_choice(choice: &mut menu::Choice, loaded_path: &str) {
+    choice.clear();
+    for (i, entry) in catalog::list().into_iter().enumerate() {
+        choice.add_choice(&entry.name);
+        if entry.path().as_os_str() == loaded_path {
+            choice.set_value(i as i32);
         }
-    } else {
-        None
     }
 }
 
-fn save_config(db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let config_path = get_config_path();
-    let mut ini = Ini::new();
-    ini.with_section(Some("database")).set("path", db_path);
-
-    ini.write_to_file(&config_path)?;
-    Ok(())
+/// Shows the progress of `download` in a modal that can cancel it, calling `on_complete` with the
+/// event it ended with. Any warnings are shown once it has.
+fn show_download_modal<C>(title: &str, download: downloader::Download, on_complete: C)
+where
+    C: FnOnce(downloader::DownloadEvent) + 'static,
+{
+    let warnings = Rc::new(RefCell::new(vec![]));
+    let collected = warnings.clone();
+    show_progress_modal(
+        title,
+        download.events,
+        Some(download.cancel),
+        move |event| match event {
+            downloader::DownloadEvent::Progress(progress) => {
+                Some((progress.fraction(), progress.to_string()))
+            }
+            // Keeps the modal open for the next source
+            downloader::DownloadEvent::SourceFailed { .. } => {
+                Some((0.0, "Failed, trying the next source".to_string()))
+            }
+            downloader::DownloadEvent::Warning(warning) => {
+                collected.borrow_mut().push(warning.clone());
+                Some((1.0, "Installing".to_string()))
+            }
+            _ => None,
+        },
+        move |event| {
+            on_complete(event);
+            let warnings = warnings.take();
+            if !warnings.is_empty() {
+                fltk::dialog::alert_default(&format!("Warning: {}", warnings.join("\n")));
+            }
+        },
+    );
 }
 
 fn main() {
@@ -124,7 +161,9 @@ fn main() {
         .with_size(800, 600)
         .with_label("IP Info GUI");
 
-    let mmdb = Rc::new(RefCell::new(None));
+    let mmdb: Rc<RefCell<Option<Arc<mmdb::reload::ReloadableMmdb>>>> = Rc::new(RefCell::new(None));
+    let reload_events: Rc<RefCell<Option<Receiver<mmdb::reload::ReloadEvent>>>> =
+        Rc::new(RefCell::new(None));
     let last_query_time = Rc::new(RefCell::new(0u64));
 
     let (s, r) = app::channel::<Message>();
@@ -170,13 +209,23 @@ fn main() {
         let mut download_btn = button::Button::default().with_label("Download default");
         row.fixed(&download_btn, 150);
 
+        let mut update_btn = button::Button::default().with_label("Check for updates");
+        update_btn.set_tooltip("Replace the default database with the latest version, if it has changed since it was installed");
+        row.fixed(&update_btn, 150);
+
+        let mut versions_btn = button::Button::default().with_label("Versions...");
+        versions_btn.set_tooltip(
+            "List the kept previous versions of this database, and roll back to one of them",
+        );
+        row.fixed(&versions_btn, 100);
+
         row.end();
         col.fixed(&row, 30);
 
         let mut input_bar2 = input_bar.clone();
         browse_button.set_callback(move |_| {
             let mut chooser = NativeFileChooser::new(fltk::dialog::FileDialogType::BrowseFile);
-            chooser.set_filter("MMDB files\t*.mmdb");
+            chooser.set_filter("MMDB files\t*.{mmdb,gz,tgz,tar}");
             chooser.show();
             let path = chooser.filename();
             if !path.as_os_str().is_empty() {
@@ -186,56 +235,151 @@ fn main() {
         });
 
         let btn = download_btn.clone();
+        let update_btn2 = update_btn.clone();
         let row = row.clone();
         let input_bar = input_bar.clone();
         let mut input_bar2 = input_bar.clone();
         let mmdb = mmdb.clone();
+        let download_buffer = buffer.clone();
         download_btn.set_callback(move |_| {
-            let (rx, _handle) = downloader::download_default_mmdb();
-
             let mut btn = btn.clone();
+            let mut update_btn = update_btn2.clone();
             let row = row.clone();
             let mut input_bar = input_bar.clone();
-            show_progress_modal(
+            let mut buffer = download_buffer.clone();
+            let config = config::load_config();
+            let sources = config.database_sources(downloader::download_url());
+            let verification = match config.database_verification(&sources) {
+                Ok(verification) => verification,
+                Err(err) => {
+                    buffer.set_text(&format!("Failed to download database: {err}"));
+                    return;
+                }
+            };
+            show_download_modal(
                 "Downloading...",
-                rx,
-                |event| match event {
-                    downloader::DownloadEvent::Progress(p) => {
-                        Some((*p, "Downloading...".to_string()))
-                    }
-                    downloader::DownloadEvent::Done(_) => None,
-                },
-                move |event| {
-                    if let downloader::DownloadEvent::Done(path_buf) = event {
+                downloader::download_default_mmdb(sources, verification),
+                move |event| match event {
+                    downloader::DownloadEvent::Done(path_buf) => {
                         btn.hide();
+                        update_btn.show();
                         row.recalc();
                         input_bar.set_value(path_buf.to_str().unwrap());
                     }
+                    downloader::DownloadEvent::Error(err) => {
+                        buffer.set_text(&format!("Failed to download database: {err}"));
+                    }
+                    _ => {}
                 },
             );
         });
+
+        {
+            let input_bar = input_bar2.clone();
+            let buffer = buffer.clone();
+            update_btn.set_callback(move |_| {
+                let config = config::load_config();
+                let sources = config.database_sources(downloader::UPDATE_URL);
+
+                let mut input_bar = input_bar.clone();
+                let mut buffer = buffer.clone();
+                let verification = match config.database_verification(&sources) {
+                    Ok(verification) => verification,
+                    Err(err) => {
+                        buffer.set_text(&format!("Failed to update database: {err}"));
+                        return;
+                    }
+                };
+                show_download_modal(
+                    "Checking for updates...",
+                    downloader::update_default_mmdb(sources, verification),
+                    move |event| match event {
+                        downloader::DownloadEvent::Done(path_buf) => {
+                            // An already loaded default database is reloaded by its watcher
+                            if input_bar.value() != path_buf.to_string_lossy() {
+                                input_bar.set_value(&path_buf.to_string_lossy());
+                                input_bar.do_callback();
+                            }
+                            buffer.set_text("Database updated");
+                        }
+                        downloader::DownloadEvent::UpToDate(_) => {
+                            buffer.set_text("Database is already up to date");
+                        }
+                        downloader::DownloadEvent::Error(err) => {
+                            buffer.set_text(&format!("Failed to update database: {err}"));
+                        }
+                        _ => {}
+                    },
+                );
+            });
+        }
+        {
+            let input_bar = input_bar2.clone();
+            let mut buffer = buffer.clone();
+            versions_btn.set_callback(move |_| {
+                let path = std::path::PathBuf::from(input_bar.value());
+                let versions = downloader::list_versions(&path);
+                if versions.is_empty() {
+                    buffer.set_text("No previous versions of this database are kept");
+                    return;
+                }
+                let built = mmdb::Mmdb::open(&path)
+                    .ok()
+                    .and_then(|mmdb| mmdb.build_time());
+                buffer.set_text(&downloader::describe_versions(&path, built));
+
+                let Some(number) = fltk::dialog::input_default(
+                    &format!("Version to roll back to (1-{}):", versions.len()),
+                    "1",
+                ) else {
+                    return;
+                };
+                let Some(version) = number
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| versions.get(number.checked_sub(1)?))
+                else {
+                    buffer.set_text(&format!("No version '{}' is kept", number.trim()));
+                    return;
+                };
+                // The loaded database is reloaded by its watcher
+                match downloader::rollback(&path, version) {
+                    Ok(()) => buffer.set_text(&format!("Rolled back to version {}", number.trim())),
+                    Err(err) => buffer.set_text(&format!("Failed to roll back: {err}")),
+                }
+            });
+        }
         let mut buffer = buffer.clone();
         let mmdb = mmdb.clone();
+        let reload_events = reload_events.clone();
         input_bar2.set_callback(move |i| {
             *mmdb.borrow_mut() = None;
+            *reload_events.borrow_mut() = None;
             let value = i.value();
             if std::fs::exists(&value).unwrap_or(false) {
-                let Ok(file) = File::open(&value) else {
-                    buffer.set_text("Failed to load database (error during file open)");
-                    return;
-                };
-                let file = BufReader::new(file);
-                let new_mmdb = match mmdb::Mmdb::new(file) {
-                    Ok(mmdb) => mmdb,
+                let new_mmdb = match mmdb::reload::ReloadableMmdb::open(&value) {
+                    Ok(mmdb) => Arc::new(mmdb),
                     Err(err) => {
                         buffer.set_text(&format!("Error while reading database: {err:?}"));
                         return;
                     }
                 };
+                let (events, _handle) = new_mmdb.watch(Duration::from_secs(2));
+                *reload_events.borrow_mut() = Some(events);
+                let mut current = new_mmdb.current();
+                let metadata = current.get_metadata_string();
+                let age = downloader::describe_database_age(
+                    Some(std::path::Path::new(&value)),
+                    current.build_time(),
+                );
                 *mmdb.borrow_mut() = Some(new_mmdb);
-                match (*mmdb.borrow_mut()).as_mut().unwrap().get_metadata_string() {
+                match metadata {
                     Ok(metadata) => {
                         buffer.set_text(&metadata);
+                        for line in age {
+                            buffer.append(&format!("\n{line}"));
+                        }
                     }
                     Err(err) => {
                         buffer.set_text(&format!(
@@ -248,10 +392,13 @@ fn main() {
             }
         });
 
-        let config_db_path = load_config();
+        let config_db_path = config::load_config().database_path;
         let db_to_load = match config_db_path {
             Some(path) if std::path::Path::new(&path).exists() => {
                 download_btn.hide();
+                if !downloader::default_mmdb_exists() {
+                    update_btn.hide();
+                }
                 path
             }
             _ => {
@@ -263,6 +410,7 @@ fn main() {
                         .to_string_lossy()
                         .to_string()
                 } else {
+                    update_btn.hide();
                     String::new()
                 }
             }
@@ -276,6 +424,143 @@ fn main() {
         db_input_bar = input_bar2.clone();
     }
 
+    {
+        let mut row = Flex::default().row();
+        let input_label = fltk::frame::Frame::default().with_label("Catalog:");
+        row.fixed(&input_label, 115);
+        let mut catalog_choice = menu::Choice::default();
+        catalog_choice.set_tooltip("Databases installed under a name, choose one to load it");
+        let mut install_btn = button::Button::default().with_label("Install...");
+        install_btn.set_tooltip("Download a database into the catalog under a name");
+        row.fixed(&install_btn, 150);
+        let mut remove_btn = button::Button::default().with_label("Remove");
+        remove_btn.set_tooltip("Delete the chosen database from the catalog");
+        row.fixed(&remove_btn, 150);
+        row.end();
+        col.fixed(&row, 30);
+
+        fill_catalog_choice(&mut catalog_choice, &db_input_bar.value());
+
+        let mut db_input_bar2 = db_input_bar.clone();
+        catalog_choice.set_callback(move |choice| {
+            if let Some(name) = choice.choice()
+                && let Ok(entry) = catalog::get(&name)
+            {
+                db_input_bar2.set_value(&entry.path().to_string_lossy());
+                db_input_bar2.do_callback();
+            }
+        });
+
+        let catalog_choice2 = catalog_choice.clone();
+        let db_input_bar2 = db_input_bar.clone();
+        let mut buffer2 = buffer.clone();
+        install_btn.set_callback(move |_| {
+            let Some(name) = fltk::dialog::input_default("Name to install the database under:", "")
+            else {
+                return;
+            };
+            let Some(url) = fltk::dialog::input_default("URL to download it from:", "") else {
+                return;
+            };
+            let Some(sha256) = fltk::dialog::input_default(
+                "SHA-256 it has to have (leave empty to skip the check):",
+                "",
+            ) else {
+                return;
+            };
+            let sha256 = Some(sha256.trim()).filter(|sha256| !sha256.is_empty());
+            let verification =
+                match downloader::Verification::with_user_checks(&url, sha256, None, None) {
+                    Ok(verification) => verification,
+                    Err(err) => {
+                        buffer2.set_text(&format!("Cannot install database: {err}"));
+                        return;
+                    }
+                };
+            let download = match catalog::install(name.trim().to_string(), url, verification) {
+                Ok(download) => download,
+                Err(err) => {
+                    buffer2.set_text(&format!("Cannot install database: {err}"));
+                    return;
+                }
+            };
+
+            let mut catalog_choice = catalog_choice2.clone();
+            let mut db_input_bar = db_input_bar2.clone();
+            let mut buffer = buffer2.clone();
+            show_download_modal("Installing...", download, move |event| match event {
+                downloader::DownloadEvent::Done(path_buf) => {
+                    db_input_bar.set_value(&path_buf.to_string_lossy());
+                    db_input_bar.do_callback();
+                    fill_catalog_choice(&mut catalog_choice, &db_input_bar.value());
+                }
+                downloader::DownloadEvent::Error(err) => {
+                    buffer.set_text(&format!("Failed to install database: {err}"));
+                }
+                _ => {}
+            });
+        });
+
+        let mut catalog_choice2 = catalog_choice.clone();
+        let mut db_input_bar2 = db_input_bar.clone();
+        let mut buffer2 = buffer.clone();
+        remove_btn.set_callback(move |_| {
+            let Some(name) = catalog_choice2.choice() else {
+                buffer2.set_text("No catalog database chosen to remove");
+                return;
+            };
+            let confirmed = fltk::dialog::choice2_default(
+                &format!("Delete '{name}' from the catalog?"),
+                "Cancel",
+                "Delete",
+                "",
+            );
+            if confirmed != Some(1) {
+                return;
+            }
+            // Unload it first, rather than keep using a deleted database
+            if catalog::database_path(&name).as_os_str() == db_input_bar2.value().as_str() {
+                db_input_bar2.set_value("");
+                db_input_bar2.do_callback();
+            }
+            match catalog::remove(&name) {
+                Ok(()) => buffer2.set_text(&format!("Removed '{name}' from the catalog")),
+                Err(err) => buffer2.set_text(&format!("Cannot remove database: {err}")),
+            }
+            fill_catalog_choice(&mut catalog_choice2, &db_input_bar2.value());
+        });
+    }
+
+    let mut dns_input_bar: input::Input;
+    let reverse_checkbox: button::CheckButton;
+    let records_checkbox: button::CheckButton;
+    {
+        let mut row = Flex::default().row();
+        let input_label = fltk::frame::Frame::default().with_label("DNS servers:");
+        row.fixed(&input_label, 115);
+        let mut input_bar = input::Input::default();
+        input_bar.set_tooltip(
+            "Comma separated DNS servers to resolve domains with, leave empty to use the system resolvers.\nDNS over HTTPS and TLS servers are given as https://host/dns-query or tls://ip[:port][#name]",
+        );
+        if let Some(servers) = config::load_config().dns_servers {
+            input_bar.set_value(&servers);
+        }
+        let mut checkbox = button::CheckButton::default().with_label("Reverse DNS");
+        checkbox.set_tooltip("Also look up the hostname of queried IP addresses");
+        row.fixed(&checkbox, 120);
+        let mut checkbox2 = button::CheckButton::default().with_label("DNS records");
+        checkbox2.set_tooltip(
+            "Look up the MX, NS, TXT and SOA records of queried domains instead, with info on the addresses of their mail and name servers",
+        );
+        row.fixed(&checkbox2, 120);
+        row.end();
+        col.fixed(&row, 30);
+
+        dns_input_bar = input_bar;
+        reverse_checkbox = checkbox;
+        records_checkbox = checkbox2;
+    }
+
     {
         let mut row = Flex::default().row();
 
@@ -304,17 +589,14 @@ fn main() {
         row.end();
         col.fixed(&row, 30);
 
-        let s = s.clone();
         metadata_button.set_callback(move |_| {
             s.send(Message::ShowMetadata);
         });
 
-        let s = s.clone();
         save_config_button.set_callback(move |_| {
             s.send(Message::SaveConfig);
         });
 
-        let s = s.clone();
         reload_config_button.set_callback(move |_| {
             s.send(Message::ReloadConfig);
         });
@@ -326,8 +608,26 @@ fn main() {
         let _reload_config_button = reload_config_button.clone();
         let last_query_time = last_query_time.clone();
         let mut query_time_input = query_time_input.clone();
+        let reload_events = reload_events.clone();
+        let mut buffer = buffer.clone();
 
         app::add_idle3(move |_handle| {
+            let event = match reload_events.borrow().as_ref().map(|rx| rx.try_recv()) {
+                Some(Ok(event)) => Some(event),
+                _ => None,
+            };
+            match event {
+                Some(mmdb::reload::ReloadEvent::Reloaded) => {
+                    buffer.set_text("Database changed on disk and was reloaded");
+                }
+                Some(mmdb::reload::ReloadEvent::Rejected(err)) => {
+                    buffer.set_text(&format!(
+                        "Database changed on disk but failed verification, still using the previous version: {err:?}"
+                    ));
+                }
+                None => {}
+            }
+
             let is_loaded = mmdb.borrow().is_some();
             let current_state = db_loaded_checkbox.is_checked();
             if is_loaded != current_state {
@@ -373,14 +673,24 @@ fn main() {
         }
     }
 
+    // Reverse lookups finish in the background, and are only shown if no other query was made since
+    let mut last_query = String::new();
+
     while app.wait() {
         if let Some(msg) = r.recv() {
             match msg {
                 Message::SaveConfig => {
                     if let Some(_mmdb) = mmdb.borrow().as_ref() {
                         let db_path = db_input_bar.value();
-                        if !db_path.is_empty() && std::path::Path::new(&db_path).exists() {
-                            match save_config(&db_path) {
+                        let dns_servers = dns_input_bar.value();
+                        let mut config = config::load_config();
+                        config.database_path = Some(db_path.clone());
+                        config.dns_servers = Some(dns_servers.trim().to_string())
+                            .filter(|servers| !servers.is_empty());
+                        if let Err(err) = config.resolver_config() {
+                            buffer.set_text(&format!("Cannot save config: {err}"));
+                        } else if !db_path.is_empty() && std::path::Path::new(&db_path).exists() {
+                            match config::save_config(&config) {
                                 Ok(()) => {
                                     buffer.set_text("Configuration saved successfully");
                                 }
@@ -396,8 +706,9 @@ fn main() {
                     }
                 }
                 Message::ReloadConfig => {
-                    let config_db_path = load_config();
-                    match config_db_path {
+                    let config = config::load_config();
+                    dns_input_bar.set_value(config.dns_servers.as_deref().unwrap_or_default());
+                    match config.database_path {
                         Some(path) if std::path::Path::new(&path).exists() => {
                             db_input_bar.set_value(&path);
                             db_input_bar.do_callback();
@@ -411,10 +722,18 @@ fn main() {
                     }
                 }
                 Message::ShowMetadata => {
-                    if let Some(mmdb) = mmdb.borrow_mut().as_mut() {
-                        match mmdb.get_metadata_string() {
+                    if let Some(mmdb) = mmdb.borrow().as_ref() {
+                        let mut current = mmdb.current();
+                        match current.get_metadata_string() {
                             Ok(metadata) => {
                                 buffer.set_text(&metadata);
+                                let db_path = db_input_bar.value();
+                                for line in downloader::describe_database_age(
+                                    Some(std::path::Path::new(&db_path)),
+                                    current.build_time(),
+                                ) {
+                                    buffer.append(&format!("\n{line}"));
+                                }
                                 display.set_insert_position(buffer.length());
                                 display.show_insert_position();
                             }
@@ -426,25 +745,67 @@ fn main() {
                         buffer.set_text("No database loaded to show metadata");
                     }
                 }
+                Message::ReverseResolved { query, info, error } => {
+                    if query == last_query {
+                        let mut text = info.to_string();
+                        if let Some(error) = error {
+                            text.push_str(&format!("\n\nReverse DNS lookup failed: {error}"));
+                        }
+                        buffer.set_text(&text);
+                    }
+                }
+                Message::RecordsResolved { query, text } => {
+                    if query == last_query {
+                        buffer.set_text(&text);
+                    }
+                }
                 Message::SendQuery(msg) => {
-                    if let Some(_mmdb_ref) = mmdb.borrow_mut().as_mut() {
+                    last_query = msg.clone();
+                    if let Some(mmdb_handle) = mmdb.borrow().as_ref() {
                         let is_domain = msg.parse::<IpAddr>().is_err();
 
-                        if is_domain {
+                        // The servers come from the settings row, the rest from the config file
+                        let mut config = config::load_config();
+                        config.dns_servers = Some(dns_input_bar.value());
+                        let resolver_config = match config.resolver_config() {
+                            Ok(resolver_config) => resolver_config,
+                            Err(err) => {
+                                buffer.set_text(&format!("Error in DNS settings: {err}"));
+                                continue;
+                            }
+                        };
+
+                        if is_domain && records_checkbox.is_checked() {
+                            buffer.set_text(&format!("Looking up DNS records for '{msg}'..."));
+                            let mut current = mmdb_handle.current();
+                            current.set_resolver_config(resolver_config);
+                            let query = msg.clone();
+                            std::thread::spawn(move || {
+                                let text = match current.query_domain_records(&query) {
+                                    Ok(info) => info.to_string(),
+                                    Err(err) => format!("Error during DNS lookup: {err:?}"),
+                                };
+                                s.send(Message::RecordsResolved { query, text });
+                            });
+                        } else if is_domain {
                             let (tx, rx) = std::sync::mpsc::channel::<mmdb::QueryProgress>();
                             let (result_tx, result_rx) =
-                                std::sync::mpsc::channel::<Result<IpAddr, mmdb::MmdbError>>();
+                                std::sync::mpsc::channel::<Result<Vec<IpAddr>, mmdb::MmdbError>>();
 
                             let msg_clone = msg.clone();
                             let tx_clone = tx.clone();
+                            let family = mmdb_handle.current().address_family();
 
                             std::thread::spawn(move || {
                                 let _ = tx_clone.send(mmdb::QueryProgress::Started);
 
-                                match mmdb::dns::query_dns_for_domain(&msg_clone) {
-                                    Ok(ip) => {
+                                match mmdb::dns::resolve(&resolver_config, &msg_clone, family) {
+                                    Ok(records) => {
                                         let _ = tx_clone.send(mmdb::QueryProgress::DnsResolved);
-                                        let _ = result_tx.send(Ok(ip));
+                                        let _ = result_tx.send(Ok(records
+                                            .into_iter()
+                                            .map(|record| record.ip)
+                                            .collect()));
                                         let _ = tx_clone.send(mmdb::QueryProgress::Completed);
                                     }
                                     Err(e) => {
@@ -462,38 +823,26 @@ fn main() {
                             show_progress_modal(
                                 "Resolving DNS...",
                                 rx,
+                                None,
                                 |event| {
                                     event
                                         .to_progress()
                                         .map(|x| (x, "Resolving domain...".to_string()))
                                 },
-                                move |_final_event| {
-                                    if let Ok(Ok(ip)) = result_rx.try_recv() {
+                                move |_final_event| match result_rx.try_recv() {
+                                    Ok(Ok(ips)) => {
                                         let start_time = Instant::now();
 
-                                        if let Some(mmdb_ref) = mmdb.borrow_mut().as_mut() {
-                                            let result = mmdb_ref.query_ip(ip);
+                                        if let Some(mmdb_handle) = mmdb.borrow().as_ref() {
+                                            let result = mmdb_handle.current().query_ips(&ips);
                                             let elapsed_ns = start_time.elapsed().as_nanos() as u64;
                                             *last_query_time.borrow_mut() = elapsed_ns;
 
                                             match result {
                                                 Ok(data) => {
-                                                    let mut output = String::new();
-                                                    output.push_str(&format!(
-                                                        "DNS: Resolved domain '{}' -> {}\n",
-                                                        msg, ip
-                                                    ));
-                                                    match data {
-                                                        Some(res) => {
-                                                            output.push_str(&format!("{res}"));
-                                                        }
-                                                        None => {
-                                                            output.push_str(&format!(
-                                                                "No data found for IP '{ip}'"
-                                                            ));
-                                                        }
-                                                    }
-                                                    buffer.set_text(&output);
+                                                    let info =
+                                                        mmdb::MmdbInfo::for_domain(&msg, ips, data);
+                                                    buffer.set_text(&info.to_string());
                                                 }
                                                 Err(err) => {
                                                     buffer.set_text(&format!(
@@ -502,41 +851,46 @@ fn main() {
                                                 }
                                             }
                                         }
-                                    } else if let Ok(Err(e)) = result_rx.try_recv() {
+                                    }
+                                    Ok(Err(e)) => {
                                         buffer.set_text(&format!(
                                             "Error during DNS resolution: {e:?}"
                                         ));
                                     }
+                                    Err(_) => {}
                                 },
                             );
                         } else {
                             buffer.set_text("");
                             let start_time = Instant::now();
-                            let result = _mmdb_ref.query_string(&msg);
+                            let result = mmdb_handle.current().query_string(&msg);
                             let elapsed_ns = start_time.elapsed().as_nanos() as u64;
                             *last_query_time.borrow_mut() = elapsed_ns;
 
                             match result {
                                 Ok(info) => {
-                                    let mut output = String::new();
-                                    if let Some(dns_info) = &info.dns_info {
-                                        output.push_str(&format!(
-                                            "DNS: Resolved domain '{}' -> {}\n",
-                                            dns_info.domain, dns_info.resolved_ip
-                                        ));
-                                    }
-                                    match info.data {
-                                        Some(res) => {
-                                            output.push_str(&format!("{res}"));
-                                        }
-                                        None => {
-                                            output
-                                                .push_str(&format!("No data found for IP '{msg}'"));
-                                        }
-                                    }
-                                    buffer.set_text(&output);
+                                    buffer.set_text(&info.to_string());
                                     display.set_insert_position(buffer.length());
                                     display.show_insert_position();
+
+                                    if reverse_checkbox.is_checked()
+                                        && let Ok(ip) = msg.parse::<IpAddr>()
+                                    {
+                                        let query = msg.clone();
+                                        std::thread::spawn(move || {
+                                            let mut info = info;
+                                            let result =
+                                                mmdb::dns::reverse_lookup(&resolver_config, ip);
+                                            let error = match result {
+                                                Ok(reverse_name) => {
+                                                    info.reverse_name = reverse_name;
+                                                    None
+                                                }
+                                                Err(err) => Some(err.to_string()),
+                                            };
+                                            s.send(Message::ReverseResolved { query, info, error });
+                                        });
+                                    }
                                 }
                                 Err(err) => {
                                     buffer.set_text(&format!("Error during query '{err:?}'"));
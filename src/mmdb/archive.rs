@@ -0,0 +1,150 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use flate2::read::MultiGzDecoder;
+
+use super::{Mmdb, MmdbError};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Reader for a database that was opened from disk, either directly from the file or from
+/// an archive that was extracted into memory, or that was embedded into the binary.
+pub enum DatabaseReader {
+    File(BufReader<File>),
+    Memory(Cursor<Vec<u8>>),
+    Embedded(Cursor<&'static [u8]>),
+}
+
+impl Read for DatabaseReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            DatabaseReader::File(reader) => reader.read(buf),
+            DatabaseReader::Memory(reader) => reader.read(buf),
+            DatabaseReader::Embedded(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for DatabaseReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            DatabaseReader::File(reader) => reader.seek(pos),
+            DatabaseReader::Memory(reader) => reader.seek(pos),
+            DatabaseReader::Embedded(reader) => reader.seek(pos),
+        }
+    }
+}
+
+impl Mmdb<DatabaseReader> {
+    /// Opens a database from a path, transparently extracting `.mmdb.gz`, `.tar` and `.tar.gz`
+    /// archives. Plain databases are read from disk as needed rather than loaded into memory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Mmdb<DatabaseReader>, MmdbError> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; TAR_MAGIC_OFFSET + TAR_MAGIC.len()];
+        let header_len = read_up_to(&mut file, &mut header)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let reader = if is_archive(&header[..header_len]) {
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            DatabaseReader::Memory(Cursor::new(extract_database(contents)?))
+        } else {
+            DatabaseReader::File(BufReader::new(file))
+        };
+        Mmdb::new(reader)
+    }
+}
+
+/// Reads a database file fully into memory, extracting it first if it is an archive.
+pub fn read_database(path: impl AsRef<Path>) -> Result<Vec<u8>, MmdbError> {
+    extract_database(std::fs::read(path)?)
+}
+
+pub fn is_archive(contents: &[u8]) -> bool {
+    contents.starts_with(GZIP_MAGIC) || is_tar(contents)
+}
+
+/// Returns the raw database contained in `contents`, which may be a gzip stream, a tarball
+/// (optionally gzipped) or already a raw database, in which case it is returned unchanged.
+pub fn extract_database(contents: Vec<u8>) -> Result<Vec<u8>, MmdbError> {
+    let contents = if contents.starts_with(GZIP_MAGIC) {
+        let mut decompressed = vec![];
+        MultiGzDecoder::new(contents.as_slice()).read_to_end(&mut decompressed)?;
+        decompressed
+    } else {
+        contents
+    };
+
+    if is_tar(&contents) {
+        extract_from_tar(&contents)
+    } else {
+        Ok(contents)
+    }
+}
+
+fn is_tar(contents: &[u8]) -> bool {
+    contents.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC)
+}
+
+fn extract_from_tar(contents: &[u8]) -> Result<Vec<u8>, MmdbError> {
+    let mut offset = 0;
+    while offset + TAR_BLOCK_SIZE <= contents.len() {
+        let header = &contents[offset..offset + TAR_BLOCK_SIZE];
+        // Archives end with zeroed blocks
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+
+        let name = tar_string(&header[0..100]);
+        let size = tar_octal(&header[124..136])?;
+        let entry_type = header[156];
+
+        let data_start = offset + TAR_BLOCK_SIZE;
+        let data_end = data_start + size;
+        if data_end > contents.len() {
+            return Err(MmdbError::InvalidArchive(
+                "tar entry extends past end of archive",
+            ));
+        }
+
+        // '0' and NUL are regular files, everything else (directories, links, pax headers) is skipped
+        let is_file = entry_type == b'0' || entry_type == 0;
+        if is_file && name.ends_with(".mmdb") {
+            return Ok(contents[data_start..data_end].to_vec());
+        }
+
+        offset = data_start + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+    }
+    Err(MmdbError::NoDatabaseInArchive)
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+fn tar_octal(field: &[u8]) -> Result<usize, MmdbError> {
+    let digits = tar_string(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| MmdbError::InvalidArchive("bad tar entry size"))
+}
+
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
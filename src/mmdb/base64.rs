@@ -0,0 +1,60 @@
+//! Base64 encoding (RFC 4648), as used for proxy credentials and DNS over HTTPS queries
+
+/// The standard alphabet (RFC 4648 section 4)
+pub static STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// The URL-safe alphabet (RFC 4648 section 5)
+pub static URL_SAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encodes `data` with `alphabet`, padding the end with `=` if `padded`
+pub fn encode(data: &[u8], alphabet: &[u8; 64], padded: bool) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        // n bytes of input make n + 1 characters of output
+        for i in 0..=chunk.len() {
+            let index = (group >> (18 - 6 * i)) & 0x3F;
+            encoded.push(alphabet[index as usize] as char);
+        }
+        if padded {
+            for _ in chunk.len()..3 {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_rfc_4648_test_vectors() {
+        let vectors = [
+            ("", "", ""),
+            ("f", "Zg==", "Zg"),
+            ("fo", "Zm8=", "Zm8"),
+            ("foo", "Zm9v", "Zm9v"),
+            ("foob", "Zm9vYg==", "Zm9vYg"),
+            ("fooba", "Zm9vYmE=", "Zm9vYmE"),
+            ("foobar", "Zm9vYmFy", "Zm9vYmFy"),
+        ];
+        for (data, padded, unpadded) in vectors {
+            assert_eq!(encode(data.as_bytes(), STANDARD, true), padded);
+            assert_eq!(encode(data.as_bytes(), STANDARD, false), unpadded);
+        }
+    }
+
+    #[test]
+    fn alphabets_differ_in_the_last_two_characters() {
+        assert_eq!(encode(&[0xFB, 0xFF], STANDARD, true), "+/8=");
+        assert_eq!(encode(&[0xFB, 0xFF], URL_SAFE, false), "-_8");
+    }
+}
@@ -0,0 +1,101 @@
+//! DNS servers configured on the network adapters, which is where Windows keeps them rather
+//! than in a resolv.conf
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use windows_sys::Win32::{
+    Foundation::{ERROR_BUFFER_OVERFLOW, NO_ERROR},
+    NetworkManagement::{
+        IpHelper::{
+            GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_FRIENDLY_NAME, GAA_FLAG_SKIP_MULTICAST,
+            GAA_FLAG_SKIP_UNICAST, GetAdaptersAddresses, IP_ADAPTER_ADDRESSES_LH,
+        },
+        Ndis::IfOperStatusUp,
+    },
+    Networking::WinSock::{
+        AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN, SOCKADDR_IN6, SOCKET_ADDRESS,
+    },
+};
+
+// Enough for a handful of adapters, a bigger buffer is asked for when it isn't
+const INITIAL_BUFFER_SIZE: u32 = 16 * 1024;
+
+/// The DNS servers of every adapter that is up, in adapter order and without duplicates
+pub fn dns_servers() -> Vec<IpAddr> {
+    let flags = GAA_FLAG_SKIP_UNICAST
+        | GAA_FLAG_SKIP_ANYCAST
+        | GAA_FLAG_SKIP_MULTICAST
+        | GAA_FLAG_SKIP_FRIENDLY_NAME;
+    let mut size = INITIAL_BUFFER_SIZE;
+    // u64s keep the buffer aligned for the structures written into it
+    let mut buffer: Vec<u64>;
+    loop {
+        buffer = vec![0; (size as usize).div_ceil(8)];
+        // SAFETY: the buffer is at least `size` bytes long
+        let result = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC as u32,
+                flags,
+                std::ptr::null(),
+                buffer.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        match result {
+            NO_ERROR => break,
+            // `size` is now what is needed, which can grow again if adapters are added meanwhile
+            ERROR_BUFFER_OVERFLOW => continue,
+            _ => return vec![],
+        }
+    }
+
+    let mut servers = vec![];
+    let mut adapter = buffer.as_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>();
+    // SAFETY: the buffer holds a linked list of adapters, each with a linked list of DNS
+    // servers, all pointing into the buffer (or null at the end)
+    unsafe {
+        while let Some(current) = adapter.as_ref() {
+            if current.OperStatus == IfOperStatusUp {
+                let mut server = current.FirstDnsServerAddress.cast_const();
+                while let Some(current_server) = server.as_ref() {
+                    if let Some(ip) = socket_address_ip(&current_server.Address)
+                        && !servers.contains(&ip)
+                    {
+                        servers.push(ip);
+                    }
+                    server = current_server.Next;
+                }
+            }
+            adapter = current.Next;
+        }
+    }
+    servers
+}
+
+/// # Safety
+/// `address` must point to a valid socket address of its family, or be null
+unsafe fn socket_address_ip(address: &SOCKET_ADDRESS) -> Option<IpAddr> {
+    // SAFETY: as guaranteed by the caller, the family says which structure it points to
+    unsafe {
+        let family = address.lpSockaddr.as_ref()?.sa_family;
+        match family {
+            AF_INET => {
+                let sockaddr = &*address.lpSockaddr.cast::<SOCKADDR_IN>();
+                // Stored in network byte order, so the octets are in memory order
+                let octets = sockaddr.sin_addr.S_un.S_addr.to_ne_bytes();
+                Some(IpAddr::V4(Ipv4Addr::from(octets)))
+            }
+            AF_INET6 => {
+                let sockaddr = &*address.lpSockaddr.cast::<SOCKADDR_IN6>();
+                let ip = Ipv6Addr::from(sockaddr.sin6_addr.u.Byte);
+                // Adapters without IPv6 DNS servers list the long deprecated site-local
+                // fec0:0:0:ffff::1-3, which nothing answers on
+                if ip.segments()[0] & 0xFFC0 == 0xFEC0 {
+                    return None;
+                }
+                Some(IpAddr::V6(ip))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, ErrorKind, Read},
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::message::{Message, RecordData, RecordType};
+
+// Upper bound on how long anything is cached, whatever its TTL says
+const MAX_TTL: u32 = 24 * 60 * 60;
+const MAX_ENTRIES: usize = 10_000;
+static DISK_CACHE_MAGIC: &[u8] = b"IPINFO-DNS-CACHE-1\n";
+
+/// Responses cached by name and record type for as long as their TTLs allow.
+///
+/// Negative answers (no such domain, or no records of the type) are cached for the minimum
+/// TTL of the zone's SOA record, as described in RFC 2308.
+#[derive(Debug, Default)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<(String, RecordType), CacheEntry>>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    message: Message,
+    expires: SystemTime,
+}
+
+impl DnsCache {
+    /// The cache shared by every resolver configuration in the process that wasn't given
+    /// its own.
+    pub fn shared() -> Arc<DnsCache> {
+        static SHARED: OnceLock<Arc<DnsCache>> = OnceLock::new();
+        SHARED.get_or_init(Default::default).clone()
+    }
+
+    /// Returns the cached response for `name`, with TTLs lowered to the time it has left.
+    pub fn get(&self, name: &str, record_type: RecordType) -> Option<Message> {
+        let key = cache_key(name, record_type);
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(&key)?;
+        let Ok(remaining) = entry.expires.duration_since(SystemTime::now()) else {
+            entries.remove(&key);
+            return None;
+        };
+
+        let remaining = u32::try_from(remaining.as_secs()).unwrap_or(u32::MAX);
+        let mut message = entry.message.clone();
+        for record in &mut message.answers {
+            record.ttl = record.ttl.min(remaining);
+        }
+        Some(message)
+    }
+
+    /// Caches the response to a query for `name`. Server failures and responses with a zero
+    /// TTL are ignored.
+    pub fn insert(&self, name: &str, record_type: RecordType, message: &Message) {
+        let Some(ttl) = cacheable_ttl(message).filter(|&ttl| ttl > 0) else {
+            return;
+        };
+        let expires = SystemTime::now() + Duration::from_secs(ttl.min(MAX_TTL) as u64);
+        let mut message = message.clone();
+        message.authorities.clear();
+        message.additionals.clear();
+
+        let key = cache_key(name, record_type);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let now = SystemTime::now();
+            entries.retain(|_, entry| entry.expires > now);
+            // Still full, make room by dropping whichever entry would have expired first
+            if entries.len() >= MAX_ENTRIES
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, CacheEntry { message, expires });
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Loads entries written by [`DnsCache::save`], skipping any that have expired since.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<DnsCache> {
+        let contents = std::fs::read(path)?;
+        let Some(contents) = contents.strip_prefix(DISK_CACHE_MAGIC) else {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "not a DNS cache file",
+            ));
+        };
+
+        let mut entries = HashMap::new();
+        let now = SystemTime::now();
+        let mut reader = Cursor::new(contents);
+        // A truncated or corrupt entry ends the file, keeping whatever was read before it
+        while let Some((message, expires)) = read_entry(&mut reader) {
+            let Some(question) = message.questions.first() else {
+                continue;
+            };
+            if expires > now {
+                let key = cache_key(&question.name, question.record_type);
+                entries.insert(key, CacheEntry { message, expires });
+            }
+        }
+        Ok(DnsCache {
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Writes the entries that haven't expired yet to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let now = SystemTime::now();
+        let mut contents = DISK_CACHE_MAGIC.to_vec();
+        for entry in self.entries.lock().unwrap().values() {
+            if entry.expires <= now {
+                continue;
+            }
+            let Ok(expires) = entry.expires.duration_since(SystemTime::UNIX_EPOCH) else {
+                continue;
+            };
+            let Ok(packet) = entry.message.to_bytes() else {
+                continue;
+            };
+            contents.write_u64::<BigEndian>(expires.as_secs())?;
+            contents.write_u32::<BigEndian>(packet.len() as u32)?;
+            contents.extend_from_slice(&packet);
+        }
+
+        // Written to the side then renamed, so a concurrent run never reads half a cache
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(temp_path, path)
+    }
+}
+
+fn read_entry(reader: &mut Cursor<&[u8]>) -> Option<(Message, SystemTime)> {
+    let expires = reader.read_u64::<BigEndian>().ok()?;
+    let length = reader.read_u32::<BigEndian>().ok()?;
+    let mut packet = vec![0; length as usize];
+    reader.read_exact(&mut packet).ok()?;
+    let message = Message::parse(&packet).ok()?;
+    Some((
+        message,
+        SystemTime::UNIX_EPOCH + Duration::from_secs(expires),
+    ))
+}
+
+fn cache_key(name: &str, record_type: RecordType) -> (String, RecordType) {
+    (name.trim_end_matches('.').to_ascii_lowercase(), record_type)
+}
+
+/// How long a response may be cached for, or `None` if it shouldn't be cached at all
+fn cacheable_ttl(message: &Message) -> Option<u32> {
+    match message.rcode() {
+        0 if !message.answers.is_empty() => message.answers.iter().map(|r| r.ttl).min(),
+        // No records of the type (NODATA), or no such domain (NXDOMAIN)
+        0 | 3 => negative_ttl(message),
+        _ => None,
+    }
+}
+
+// The TTL of the SOA record in the authority section, capped by the SOA's minimum field
+fn negative_ttl(message: &Message) -> Option<u32> {
+    message
+        .authorities
+        .iter()
+        .find_map(|record| match &record.data {
+            RecordData::Soa(soa) => Some(record.ttl.min(soa.minimum)),
+            _ => None,
+        })
+}
@@ -0,0 +1,216 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Once},
+    time::Duration,
+};
+
+use super::{
+    cache::DnsCache,
+    hosts,
+    https::HttpsTransport,
+    tls::{DNS_OVER_TLS_PORT, TlsTransport},
+    transport::{Transport, UdpTransport},
+};
+
+const DNS_PORT: u16 = 53;
+// Same defaults as glibc's resolver
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_ATTEMPTS: u32 = 2;
+// Used when the system has no usable resolver configuration
+static FALLBACK_DNS_SERVER: &str = "1.1.1.1";
+static RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+#[derive(Clone, Debug)]
+pub struct ResolverConfig {
+    /// Servers to query, tried in order until one answers
+    pub servers: Vec<Arc<dyn Transport>>,
+    /// How long to wait for a server to answer before moving on to the next one
+    pub timeout: Duration,
+    /// How many times the whole list of servers is tried before giving up
+    pub attempts: u32,
+    /// Where responses are cached, the process-wide [`DnsCache::shared`] by default.
+    /// Set to `None` to always query the servers.
+    pub cache: Option<Arc<DnsCache>>,
+    /// Hosts file consulted before any server, the system one by default
+    pub hosts_path: Option<PathBuf>,
+}
+
+impl ResolverConfig {
+    pub fn with_servers(servers: Vec<Arc<dyn Transport>>) -> ResolverConfig {
+        ResolverConfig {
+            servers,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            cache: Some(DnsCache::shared()),
+            hosts_path: Some(hosts::system_hosts_path()),
+        }
+    }
+
+    /// Nameservers from `/etc/resolv.conf`, or on Windows those of the network adapters that
+    /// are up. If there are none, a public resolver is used, with a warning.
+    pub fn system() -> ResolverConfig {
+        if let Some(config) = Self::from_resolv_conf(RESOLV_CONF_PATH) {
+            return config;
+        }
+        #[cfg(target_os = "windows")]
+        {
+            let servers = super::adapters::dns_servers();
+            if !servers.is_empty() {
+                return Self::with_servers(
+                    servers
+                        .into_iter()
+                        .map(|ip| {
+                            Arc::new(UdpTransport::new(SocketAddr::new(ip, DNS_PORT)))
+                                as Arc<dyn Transport>
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        static WARNED: Once = Once::new();
+        WARNED.call_once(|| {
+            eprintln!(
+                "WARN: no system DNS servers found, resolving domains with {FALLBACK_DNS_SERVER}"
+            );
+        });
+        Self::with_servers(vec![Arc::new(UdpTransport::new(SocketAddr::new(
+            FALLBACK_DNS_SERVER.parse().unwrap(),
+            DNS_PORT,
+        )))])
+    }
+
+    /// `servers` with the `timeout`/`attempts` options from `/etc/resolv.conf`, for servers the
+    /// user configured in place of the system ones
+    pub fn with_system_options(servers: Vec<Arc<dyn Transport>>) -> ResolverConfig {
+        let contents = std::fs::read_to_string(RESOLV_CONF_PATH).unwrap_or_default();
+        ResolverConfig {
+            servers,
+            ..parse_resolv_conf(&contents)
+        }
+    }
+
+    /// Reads the `nameserver` lines and the `timeout`/`attempts` options of a resolv.conf
+    /// file, returning `None` if the file can't be read or lists no nameservers.
+    pub fn from_resolv_conf(path: impl AsRef<Path>) -> Option<ResolverConfig> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        let config = parse_resolv_conf(&contents);
+        if config.servers.is_empty() {
+            None
+        } else {
+            Some(config)
+        }
+    }
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+fn parse_resolv_conf(contents: &str) -> ResolverConfig {
+    let mut config = ResolverConfig::with_servers(vec![]);
+    for line in contents.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => {
+                // Link-local IPv6 servers carry a zone index (fe80::1%eth0) that std can't parse
+                let address = fields.next().and_then(|x| x.split('%').next());
+                if let Some(Ok(ip)) = address.map(str::parse::<IpAddr>) {
+                    let address = SocketAddr::new(ip, DNS_PORT);
+                    config.servers.push(Arc::new(UdpTransport::new(address)));
+                }
+            }
+            Some("options") => {
+                for option in fields {
+                    match option.split_once(':') {
+                        Some(("timeout", secs)) => {
+                            if let Ok(secs) = secs.parse::<u64>() {
+                                config.timeout = Duration::from_secs(secs.max(1));
+                            }
+                        }
+                        Some(("attempts", attempts)) => {
+                            if let Ok(attempts) = attempts.parse::<u32>() {
+                                config.attempts = attempts.max(1);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    config
+}
+
+/// Parses a server given by the user:
+/// - plain DNS, with or without a port (`1.1.1.1`, `1.1.1.1:5353`, `::1` or `[::1]:5353`)
+/// - DNS over HTTPS, as the URL to query (`https://cloudflare-dns.com/dns-query`)
+/// - DNS over TLS, optionally followed by the name on the server's certificate
+///   (`tls://1.1.1.1` or `tls://1.1.1.1:853#cloudflare-dns.com`)
+pub fn parse_server(server: &str) -> Option<Arc<dyn Transport>> {
+    let server = server.trim();
+    if server.starts_with("https://") || server.starts_with("http://") {
+        return Some(Arc::new(HttpsTransport::new(server)));
+    }
+    if let Some(server) = server.strip_prefix("tls://") {
+        let (address, server_name) = match server.split_once('#') {
+            Some((address, server_name)) => (address, Some(server_name)),
+            None => (server, None),
+        };
+        let address = parse_address(address, DNS_OVER_TLS_PORT)?;
+        // Without a name, the certificate has to be issued for the IP address itself
+        let server_name = server_name.map_or_else(|| address.ip().to_string(), str::to_string);
+        return Some(Arc::new(TlsTransport::new(address, server_name)));
+    }
+    let address = parse_address(server, DNS_PORT)?;
+    Some(Arc::new(UdpTransport::new(address)))
+}
+
+fn parse_address(address: &str, default_port: u16) -> Option<SocketAddr> {
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, default_port));
+    }
+    address.parse::<SocketAddr>().ok()
+}
+
+/// Parses a comma or whitespace separated list of servers, as used in flags and config files.
+pub fn parse_server_list(servers: &str) -> Result<Vec<Arc<dyn Transport>>, String> {
+    servers
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|server| !server.trim().is_empty())
+        .map(|server| parse_server(server).ok_or_else(|| server.trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_lists_split_on_commas_and_any_whitespace() {
+        let servers =
+            parse_server_list("192.0.2.1,192.0.2.2\t192.0.2.3\n 192.0.2.4:5353 ,").unwrap();
+        let addresses: Vec<_> = servers
+            .iter()
+            .map(|server| server.udp_address().unwrap())
+            .collect();
+        assert_eq!(
+            addresses,
+            [
+                SocketAddr::from(([192, 0, 2, 1], DNS_PORT)),
+                SocketAddr::from(([192, 0, 2, 2], DNS_PORT)),
+                SocketAddr::from(([192, 0, 2, 3], DNS_PORT)),
+                SocketAddr::from(([192, 0, 2, 4], 5353)),
+            ]
+        );
+        assert_eq!(
+            parse_server_list("192.0.2.1 not-a-server").unwrap_err(),
+            "not-a-server"
+        );
+    }
+}
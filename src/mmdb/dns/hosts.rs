@@ -0,0 +1,49 @@
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use super::AddressFamily;
+
+/// Location of the system hosts file
+pub fn system_hosts_path() -> PathBuf {
+    if cfg!(target_os = "windows") {
+        let root = std::env::var_os("SystemRoot").unwrap_or_else(|| "C:\\Windows".into());
+        let mut path = PathBuf::from(root);
+        path.push("System32\\drivers\\etc\\hosts");
+        path
+    } else {
+        PathBuf::from("/etc/hosts")
+    }
+}
+
+/// Returns the addresses of the given family listed for `name` in a hosts file, IPv4
+/// addresses first. A missing or unreadable file has no entries.
+pub fn lookup(path: impl AsRef<Path>, name: &str, family: AddressFamily) -> Vec<IpAddr> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return vec![];
+    };
+    let name = name.trim_end_matches('.');
+
+    let mut ips = vec![];
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        // Link-local IPv6 entries carry a zone index (fe80::1%lo0) that std can't parse
+        let address = fields.next().and_then(|x| x.split('%').next());
+        let Some(Ok(ip)) = address.map(str::parse::<IpAddr>) else {
+            continue;
+        };
+        let wanted = match family {
+            AddressFamily::Ipv4 => ip.is_ipv4(),
+            AddressFamily::Ipv6 => ip.is_ipv6(),
+            AddressFamily::Both => true,
+        };
+        let matches = fields.any(|host| host.trim_end_matches('.').eq_ignore_ascii_case(name));
+        if wanted && matches && !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+    ips.sort_by_key(|ip| ip.is_ipv6());
+    ips
+}
@@ -0,0 +1,192 @@
+use std::{io::Read, time::Duration};
+
+use super::{
+    DnsError, DnsResult, MAX_UDP_RESPONSE_SIZE, io_error, matching_response,
+    message::{self, Message, RecordType},
+    transport::Transport,
+};
+use crate::{
+    base64,
+    http::{self, HttpSettings},
+};
+
+static DNS_MESSAGE_TYPE: &str = "application/dns-message";
+
+/// DNS over HTTPS (RFC 8484), sending queries to a URL such as
+/// `https://cloudflare-dns.com/dns-query`
+#[derive(Clone, Debug)]
+pub struct HttpsTransport {
+    pub url: String,
+    pub method: DohMethod,
+    /// Proxies and extra root certificates to trust, none by default. The timeout is the
+    /// resolver's.
+    pub settings: HttpSettings,
+}
+
+/// How DNS over HTTPS queries are sent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DohMethod {
+    /// In the `dns` query parameter, so HTTP caches can answer identical queries
+    #[default]
+    Get,
+    /// As the request body
+    Post,
+}
+
+impl HttpsTransport {
+    pub fn new(url: impl Into<String>) -> HttpsTransport {
+        HttpsTransport {
+            url: url.into(),
+            method: DohMethod::Get,
+            settings: HttpSettings::default(),
+        }
+    }
+}
+
+impl Transport for HttpsTransport {
+    fn exchange(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> DnsResult<Message> {
+        // The RFC asks for an ID of 0, so identical queries can be answered by HTTP caches
+        let packet = message::build_query(0, name, record_type, true)?;
+        let settings = HttpSettings {
+            timeout: Some(timeout),
+            ..self.settings.clone()
+        };
+        let accept = ("Accept", DNS_MESSAGE_TYPE.to_string());
+        let response = match self.method {
+            DohMethod::Get => {
+                // URL-safe and unpadded, as RFC 8484 asks
+                let separator = if self.url.contains('?') { '&' } else { '?' };
+                let url = format!(
+                    "{}{separator}dns={}",
+                    self.url,
+                    base64::encode(&packet, base64::URL_SAFE, false)
+                );
+                http::get(&url, &[accept], &settings)
+            }
+            DohMethod::Post => {
+                let content_type = ("Content-Type", DNS_MESSAGE_TYPE.to_string());
+                http::post(&self.url, &[accept, content_type], &packet, &settings)
+            }
+        }
+        .map_err(|err| DnsError::HttpsFailed(err.to_string()))?;
+        if response.status_code != 200 {
+            return Err(DnsError::HttpsFailed(format!(
+                "server responded with {} {}",
+                response.status_code, response.reason_phrase
+            )));
+        }
+
+        let mut body = vec![];
+        response
+            .take(MAX_UDP_RESPONSE_SIZE as u64)
+            .read_to_end(&mut body)
+            .map_err(|err| io_error(err, DnsError::RecvFailed))?;
+        matching_response(&body, 0, name, record_type)?.ok_or(DnsError::MalformedResponse(
+            "HTTPS response does not match the query",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+    use crate::dns::tests::{ADDRESS, NAME, answer, answered_address};
+
+    /// The query every test sends, which is always the same as its ID is 0
+    fn expected_query() -> Vec<u8> {
+        message::build_query(0, NAME, RecordType::A, true).unwrap()
+    }
+
+    /// A plain HTTP server on 127.0.0.1 that responds to one request with `status`, and the
+    /// answer to the expected query when it is 200. The request is sent back on the channel.
+    fn stand_in(status: &'static str) -> (String, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut head = String::new();
+            while reader.read_line(&mut head).unwrap() > 2 {}
+            let length = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .map_or(0, |length| length.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let answer = match status {
+                "200 OK" => answer(&Message::parse(&expected_query()).unwrap(), ADDRESS)
+                    .to_bytes()
+                    .unwrap(),
+                _ => vec![],
+            };
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: {DNS_MESSAGE_TYPE}\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                answer.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            stream.write_all(&answer).unwrap();
+            let _ = tx.send((head, body));
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn get_sends_the_query_base64url_encoded() {
+        let (url, requests) = stand_in("200 OK");
+        let message = HttpsTransport::new(url)
+            .exchange(NAME, RecordType::A, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+
+        let (head, body) = requests.recv_timeout(Duration::from_secs(1)).unwrap();
+        let parameter = base64::encode(&expected_query(), base64::URL_SAFE, false);
+        assert!(
+            head.starts_with(&format!("GET /dns-query?dns={parameter} HTTP/1.1\r\n")),
+            "{head}"
+        );
+        assert!(!parameter.contains(['+', '/', '=']));
+        assert!(head.contains(&format!("Accept: {DNS_MESSAGE_TYPE}\r\n")));
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn post_sends_the_query_as_the_body() {
+        let (url, requests) = stand_in("200 OK");
+        let mut transport = HttpsTransport::new(url);
+        transport.method = DohMethod::Post;
+        let message = transport
+            .exchange(NAME, RecordType::A, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+
+        let (head, body) = requests.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(head.starts_with("POST /dns-query HTTP/1.1\r\n"), "{head}");
+        assert!(head.contains(&format!("Content-Type: {DNS_MESSAGE_TYPE}\r\n")));
+        assert_eq!(body, expected_query());
+    }
+
+    #[test]
+    fn error_status_fails() {
+        let (url, _requests) = stand_in("503 Service Unavailable");
+        let result = HttpsTransport::new(url).exchange(NAME, RecordType::A, Duration::from_secs(5));
+        assert!(
+            matches!(&result, Err(DnsError::HttpsFailed(reason)) if reason.contains("503")),
+            "{result:?}"
+        );
+    }
+}
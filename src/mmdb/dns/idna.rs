@@ -0,0 +1,202 @@
+use super::{DnsError, DnsResult};
+
+const MAX_LABEL_LENGTH: usize = 63;
+// 255 bytes on the wire, minus the length prefix of the first label and the root label
+const MAX_NAME_LENGTH: usize = 253;
+const ACE_PREFIX: &str = "xn--";
+
+// Punycode parameters from RFC 3492
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+/// Converts a domain name into the ASCII form used on the wire, encoding non-ASCII labels
+/// with punycode (`bücher.de` becomes `xn--bcher-kva.de`), and checks label and name lengths.
+///
+/// Labels are only lowercased before encoding, rather than going through the full IDNA
+/// mapping tables, which covers names as they are normally typed.
+pub fn to_ascii(name: &str) -> DnsResult<String> {
+    let trimmed = name.strip_suffix('.').unwrap_or(name);
+    if trimmed.is_empty() {
+        return Err(DnsError::EmptyLabel(name.to_string()));
+    }
+
+    // IDNA also treats the ideographic and fullwidth full stops as label separators
+    let mut labels = vec![];
+    for label in trimmed.split(['.', '\u{3002}', '\u{FF0E}', '\u{FF61}']) {
+        if label.is_empty() {
+            return Err(DnsError::EmptyLabel(name.to_string()));
+        }
+        let label = if label.is_ascii() {
+            label.to_ascii_lowercase()
+        } else {
+            let chars = label.to_lowercase().chars().collect::<Vec<_>>();
+            let Some(encoded) = punycode_encode(&chars) else {
+                return Err(DnsError::InvalidName(name.to_string()));
+            };
+            format!("{ACE_PREFIX}{encoded}")
+        };
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(DnsError::DomainPartTooLong);
+        }
+        labels.push(label);
+    }
+
+    let ascii = labels.join(".");
+    if ascii.len() > MAX_NAME_LENGTH {
+        return Err(DnsError::NameTooLong(name.to_string()));
+    }
+    Ok(ascii)
+}
+
+/// Encodes a label as described in RFC 3492, returning `None` on overflow.
+fn punycode_encode(input: &[char]) -> Option<String> {
+    let mut output = input.iter().filter(|c| c.is_ascii()).collect::<String>();
+    let basic_count = output.len() as u32;
+    if basic_count > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut handled = basic_count;
+    while (handled as usize) < input.len() {
+        // The smallest code point that hasn't been handled yet
+        let m = input.iter().map(|&c| c as u32).filter(|&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+
+        for &c in input {
+            let c = c as u32;
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = if k <= bias {
+                        T_MIN
+                    } else if k >= bias + T_MAX {
+                        T_MAX
+                    } else {
+                        k - bias
+                    };
+                    if q < t {
+                        break;
+                    }
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(encode_digit(q));
+                bias = adapt(delta, handled + 1, handled == basic_count);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+    Some(output)
+}
+
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn encode_digit(digit: u32) -> char {
+    match digit {
+        0..26 => (b'a' + digit as u8) as char,
+        _ => (b'0' + (digit - 26) as u8) as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(label: &str) -> String {
+        punycode_encode(&label.chars().collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn punycode_matches_rfc_3492_samples() {
+        // (B) Chinese (simplified)
+        assert_eq!(
+            encode("\u{4ED6}\u{4EEC}\u{4E3A}\u{4EC0}\u{4E48}\u{4E0D}\u{8BF4}\u{4E2D}\u{6587}"),
+            "ihqwcrb4cv8a8dqg056pqjye"
+        );
+        // (D) Czech, whose basic code points keep their case
+        assert_eq!(
+            encode("Pro\u{10D}prost\u{11B}nemluv\u{ED}\u{10D}esky"),
+            "Proprostnemluvesky-uyb24dma41a"
+        );
+        // (E) Hebrew
+        assert_eq!(
+            encode(
+                "\u{5DC}\u{5DE}\u{5D4}\u{5D4}\u{5DD}\u{5E4}\u{5E9}\u{5D5}\u{5D8}\u{5DC}\u{5D0}\
+                 \u{5DE}\u{5D3}\u{5D1}\u{5E8}\u{5D9}\u{5DD}\u{5E2}\u{5D1}\u{5E8}\u{5D9}\u{5EA}"
+            ),
+            "4dbcagdahymbxekheh6e0a7fei0b"
+        );
+        // (L) 3<nen>B<gumi><kinpachi><sensei>
+        assert_eq!(
+            encode("3\u{5E74}B\u{7D44}\u{91D1}\u{516B}\u{5148}\u{751F}"),
+            "3B-ww4c5e180e575a65lsy2b"
+        );
+    }
+
+    #[test]
+    fn non_ascii_labels_are_encoded() {
+        assert_eq!(to_ascii("bücher.de").unwrap(), "xn--bcher-kva.de");
+        assert_eq!(
+            to_ascii("straße.münchen.de").unwrap(),
+            "xn--strae-oqa.xn--mnchen-3ya.de"
+        );
+        // Ideographic full stops separate labels too
+        assert_eq!(to_ascii("bücher\u{3002}de").unwrap(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn labels_are_lowercased() {
+        assert_eq!(to_ascii("BÜCHER.DE").unwrap(), "xn--bcher-kva.de");
+        assert_eq!(to_ascii("Bücher.de").unwrap(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn ascii_names_are_only_lowercased() {
+        assert_eq!(to_ascii("example.com").unwrap(), "example.com");
+        assert_eq!(to_ascii("WWW.Example.COM.").unwrap(), "www.example.com");
+        assert_eq!(to_ascii("xn--bcher-kva.de").unwrap(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        assert!(matches!(to_ascii(""), Err(DnsError::EmptyLabel(_))));
+        assert!(matches!(
+            to_ascii("example..com"),
+            Err(DnsError::EmptyLabel(_))
+        ));
+        assert!(matches!(
+            to_ascii(&format!("{}.com", "a".repeat(64))),
+            Err(DnsError::DomainPartTooLong)
+        ));
+        assert!(matches!(
+            to_ascii(&vec!["a".repeat(63); 4].join(".")),
+            Err(DnsError::NameTooLong(_))
+        ));
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    fmt,
+    io::{Cursor, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{DnsError, DnsResult};
+
+const HEADER_SIZE: usize = 12;
+// Bounds the number of compression pointers followed while reading a single name, so
+// that a malicious response with pointer loops can't hang the parser
+const MAX_POINTER_JUMPS: usize = 64;
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_LABEL_LENGTH: usize = 63;
+
+const CLASS_IN: u16 = 0x0001;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const TYPE_OPT: u16 = 41;
+/// UDP payload size advertised with EDNS0, small enough to avoid IP fragmentation on
+/// common links (as recommended by DNS Flag Day 2020)
+pub const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Mx,
+    Txt,
+    Aaaa,
+    Other(u16),
+}
+
+impl RecordType {
+    pub fn to_u16(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Ns => 2,
+            RecordType::Cname => 5,
+            RecordType::Soa => 6,
+            RecordType::Ptr => 12,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Aaaa => 28,
+            RecordType::Other(x) => x,
+        }
+    }
+
+    pub fn from_u16(value: u16) -> RecordType {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::Ns,
+            5 => RecordType::Cname,
+            6 => RecordType::Soa,
+            12 => RecordType::Ptr,
+            15 => RecordType::Mx,
+            16 => RecordType::Txt,
+            28 => RecordType::Aaaa,
+            x => RecordType::Other(x),
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordType::A => write!(f, "A"),
+            RecordType::Ns => write!(f, "NS"),
+            RecordType::Cname => write!(f, "CNAME"),
+            RecordType::Soa => write!(f, "SOA"),
+            RecordType::Ptr => write!(f, "PTR"),
+            RecordType::Mx => write!(f, "MX"),
+            RecordType::Txt => write!(f, "TXT"),
+            RecordType::Aaaa => write!(f, "AAAA"),
+            RecordType::Other(x) => write!(f, "TYPE{x}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Ns(String),
+    Cname(String),
+    Soa(Soa),
+    Ptr(String),
+    Mx {
+        /// Lower values are preferred
+        preference: u16,
+        exchange: String,
+    },
+    /// The record's character strings, with invalid UTF-8 replaced
+    Txt(Vec<String>),
+    Aaaa(Ipv6Addr),
+    Other(Vec<u8>),
+}
+
+/// Start of authority, describing the zone a name belongs to
+#[derive(Clone, Debug)]
+pub struct Soa {
+    /// Primary name server of the zone
+    pub mname: String,
+    /// Mailbox of the person responsible for the zone, with the `@` written as a `.`
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    /// How long negative answers from the zone may be cached
+    pub minimum: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Question {
+    pub name: String,
+    pub record_type: RecordType,
+    pub class: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub name: String,
+    pub record_type: RecordType,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    pub fn parse(packet: &[u8]) -> DnsResult<Message> {
+        if packet.len() < HEADER_SIZE {
+            return Err(DnsError::ResponseTooShort);
+        }
+
+        let mut reader = Cursor::new(packet);
+        let id = read_u16(&mut reader)?;
+        let flags = read_u16(&mut reader)?;
+        let question_count = read_u16(&mut reader)?;
+        let answer_count = read_u16(&mut reader)?;
+        let authority_count = read_u16(&mut reader)?;
+        let additional_count = read_u16(&mut reader)?;
+
+        let mut questions = Vec::with_capacity(question_count as usize);
+        for _ in 0..question_count {
+            let name = read_name(&mut reader)?;
+            let record_type = RecordType::from_u16(read_u16(&mut reader)?);
+            let class = read_u16(&mut reader)?;
+            questions.push(Question {
+                name,
+                record_type,
+                class,
+            });
+        }
+
+        let answers = read_records(&mut reader, answer_count)?;
+        let authorities = read_records(&mut reader, authority_count)?;
+        let additionals = read_records(&mut reader, additional_count)?;
+
+        Ok(Message {
+            id,
+            flags,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.flags & 0x000F) as u8
+    }
+
+    /// Encodes the header, questions and answers back into wire format, without name
+    /// compression. The authority and additional sections are left out, as unparsed record
+    /// data in them may point into the original packet.
+    pub fn to_bytes(&self) -> DnsResult<Vec<u8>> {
+        let (Ok(question_count), Ok(answer_count)) = (
+            u16::try_from(self.questions.len()),
+            u16::try_from(self.answers.len()),
+        ) else {
+            return Err(DnsError::MalformedResponse("too many records"));
+        };
+
+        let mut packet = vec![];
+        for field in [self.id, self.flags, question_count, answer_count, 0, 0] {
+            packet
+                .write_u16::<BigEndian>(field)
+                .map_err(DnsError::WriteFailed)?;
+        }
+        for question in &self.questions {
+            write_name(&mut packet, &question.name)?;
+            packet
+                .write_u16::<BigEndian>(question.record_type.to_u16())
+                .map_err(DnsError::WriteFailed)?;
+            packet
+                .write_u16::<BigEndian>(question.class)
+                .map_err(DnsError::WriteFailed)?;
+        }
+        for record in &self.answers {
+            write_record(&mut packet, record)?;
+        }
+        Ok(packet)
+    }
+
+    /// Whether the server cut the response short because it didn't fit in a UDP packet
+    pub fn is_truncated(&self) -> bool {
+        self.flags & FLAG_TRUNCATED != 0
+    }
+
+    /// Whether this is a response whose question is exactly `name` and `record_type`
+    pub fn is_response_to(&self, name: &str, record_type: RecordType) -> bool {
+        let is_response = self.flags & FLAG_RESPONSE != 0;
+        let name = name.trim_end_matches('.');
+        let [question] = self.questions.as_slice() else {
+            return false;
+        };
+        is_response
+            && question.record_type == record_type
+            && question.class == CLASS_IN
+            && question.name.eq_ignore_ascii_case(name)
+    }
+
+    /// Follows CNAME records in the answer section starting at `name`, returning the records
+    /// of `record_type` owned by the end of the chain along with the name the chain ended at.
+    pub fn follow_cnames(&self, name: &str, record_type: RecordType) -> (Vec<&Record>, String) {
+        let mut name = name.trim_end_matches('.').to_string();
+        // A chain can't be longer than the answer section without looping
+        for _ in 0..=self.answers.len() {
+            let records = self
+                .answers
+                .iter()
+                .filter(|r| r.record_type == record_type && r.name.eq_ignore_ascii_case(&name))
+                .collect::<Vec<_>>();
+            if !records.is_empty() {
+                return (records, name);
+            }
+
+            let alias = self.answers.iter().find_map(|r| match &r.data {
+                RecordData::Cname(target) if r.name.eq_ignore_ascii_case(&name) => Some(target),
+                _ => None,
+            });
+            match alias {
+                Some(target) => name = target.clone(),
+                None => break,
+            }
+        }
+        (vec![], name)
+    }
+}
+
+/// Builds a query packet, with an EDNS0 OPT record advertising [`EDNS_UDP_PAYLOAD_SIZE`]
+/// if `edns` is set.
+pub fn build_query(id: u16, name: &str, record_type: RecordType, edns: bool) -> DnsResult<Vec<u8>> {
+    let mut packet = vec![];
+
+    // Header
+    packet
+        .write_u16::<BigEndian>(id)
+        .map_err(DnsError::WriteFailed)?;
+
+    // Flags
+    #[allow(clippy::unusual_byte_groupings)]
+    packet
+        .write_u16::<BigEndian>(0b0_0000_0_0_1_0_000_0000)
+        .map_err(DnsError::WriteFailed)?;
+
+    // COUNTS
+    packet
+        .write_u16::<BigEndian>(1)
+        .map_err(DnsError::WriteFailed)?; // QDCOUNT
+    packet
+        .write_u16::<BigEndian>(0)
+        .map_err(DnsError::WriteFailed)?; // ANCOUNT
+    packet
+        .write_u16::<BigEndian>(0)
+        .map_err(DnsError::WriteFailed)?; // NSCOUNT
+    packet
+        .write_u16::<BigEndian>(edns as u16)
+        .map_err(DnsError::WriteFailed)?; // ARCOUNT
+
+    // Question
+
+    // QNAME
+    write_name(&mut packet, name)?;
+
+    // QTYPE
+    packet
+        .write_u16::<BigEndian>(record_type.to_u16())
+        .map_err(DnsError::WriteFailed)?;
+    // QCLASS
+    packet
+        .write_u16::<BigEndian>(CLASS_IN)
+        .map_err(DnsError::WriteFailed)?;
+
+    if edns {
+        // OPT pseudo-record: root name, the payload size in place of the class, and the
+        // extended rcode, version and flags (all zero) in place of the TTL
+        packet.write_u8(0).map_err(DnsError::WriteFailed)?;
+        packet
+            .write_u16::<BigEndian>(TYPE_OPT)
+            .map_err(DnsError::WriteFailed)?;
+        packet
+            .write_u16::<BigEndian>(EDNS_UDP_PAYLOAD_SIZE)
+            .map_err(DnsError::WriteFailed)?;
+        packet
+            .write_u32::<BigEndian>(0)
+            .map_err(DnsError::WriteFailed)?;
+        packet
+            .write_u16::<BigEndian>(0)
+            .map_err(DnsError::WriteFailed)?; // RDLENGTH
+    }
+
+    Ok(packet)
+}
+
+/// Writes a name as uncompressed labels
+fn write_name(packet: &mut Vec<u8>, name: &str) -> DnsResult<()> {
+    let name = name.trim_end_matches('.');
+    // The root name is just the terminating empty label
+    if !name.is_empty() {
+        for part in name.split('.') {
+            let part = part.as_bytes();
+            if part.len() > MAX_LABEL_LENGTH {
+                return Err(DnsError::DomainPartTooLong);
+            }
+            packet
+                .write_u8(part.len() as u8)
+                .map_err(DnsError::WriteFailed)?;
+            packet.write_all(part).map_err(DnsError::WriteFailed)?;
+        }
+    }
+    packet.write_u8(0).map_err(DnsError::WriteFailed)?;
+    Ok(())
+}
+
+fn write_record(packet: &mut Vec<u8>, record: &Record) -> DnsResult<()> {
+    let mut data = vec![];
+    match &record.data {
+        RecordData::A(ip) => data.extend_from_slice(&ip.octets()),
+        RecordData::Aaaa(ip) => data.extend_from_slice(&ip.octets()),
+        RecordData::Ns(name) | RecordData::Cname(name) | RecordData::Ptr(name) => {
+            write_name(&mut data, name)?
+        }
+        RecordData::Soa(soa) => {
+            write_name(&mut data, &soa.mname)?;
+            write_name(&mut data, &soa.rname)?;
+            for field in [soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+        }
+        RecordData::Mx {
+            preference,
+            exchange,
+        } => {
+            data.extend_from_slice(&preference.to_be_bytes());
+            write_name(&mut data, exchange)?;
+        }
+        RecordData::Txt(strings) => {
+            for string in strings {
+                let Ok(length) = u8::try_from(string.len()) else {
+                    return Err(DnsError::MalformedResponse("TXT string too long"));
+                };
+                data.push(length);
+                data.extend_from_slice(string.as_bytes());
+            }
+        }
+        RecordData::Other(bytes) => data.extend_from_slice(bytes),
+    }
+    let Ok(data_length) = u16::try_from(data.len()) else {
+        return Err(DnsError::MalformedResponse("record data too long"));
+    };
+
+    write_name(packet, &record.name)?;
+    packet
+        .write_u16::<BigEndian>(record.record_type.to_u16())
+        .map_err(DnsError::WriteFailed)?;
+    packet
+        .write_u16::<BigEndian>(record.class)
+        .map_err(DnsError::WriteFailed)?;
+    packet
+        .write_u32::<BigEndian>(record.ttl)
+        .map_err(DnsError::WriteFailed)?;
+    packet
+        .write_u16::<BigEndian>(data_length)
+        .map_err(DnsError::WriteFailed)?;
+    packet.write_all(&data).map_err(DnsError::WriteFailed)?;
+    Ok(())
+}
+
+fn read_records(reader: &mut Cursor<&[u8]>, count: u16) -> DnsResult<Vec<Record>> {
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_name(reader)?;
+        let record_type = RecordType::from_u16(read_u16(reader)?);
+        let class = read_u16(reader)?;
+        let ttl = reader
+            .read_u32::<BigEndian>()
+            .map_err(|_| DnsError::ResponseTooShort)?;
+        let data_length = read_u16(reader)? as usize;
+
+        let data_start = reader.position() as usize;
+        let data_end = data_start + data_length;
+        if data_end > reader.get_ref().len() {
+            return Err(DnsError::ResponseTooShort);
+        }
+
+        let data = match record_type {
+            RecordType::A => {
+                let mut octets = [0u8; 4];
+                if data_length != octets.len() {
+                    return Err(DnsError::MalformedResponse("A record is not 4 bytes"));
+                }
+                reader
+                    .read_exact(&mut octets)
+                    .map_err(|_| DnsError::ResponseTooShort)?;
+                RecordData::A(Ipv4Addr::from(octets))
+            }
+            RecordType::Ns => RecordData::Ns(read_name(reader)?),
+            RecordType::Cname => RecordData::Cname(read_name(reader)?),
+            RecordType::Soa => {
+                let mname = read_name(reader)?;
+                let rname = read_name(reader)?;
+                let mut fields = [0u32; 5];
+                for field in &mut fields {
+                    *field = reader
+                        .read_u32::<BigEndian>()
+                        .map_err(|_| DnsError::ResponseTooShort)?;
+                }
+                let [serial, refresh, retry, expire, minimum] = fields;
+                RecordData::Soa(Soa {
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                })
+            }
+            RecordType::Ptr => RecordData::Ptr(read_name(reader)?),
+            RecordType::Mx => RecordData::Mx {
+                preference: read_u16(reader)?,
+                exchange: read_name(reader)?,
+            },
+            RecordType::Txt => {
+                let mut strings = vec![];
+                let mut data = &reader.get_ref()[data_start..data_end];
+                while let Some((&length, rest)) = data.split_first() {
+                    let Some(string) = rest.get(..length as usize) else {
+                        return Err(DnsError::MalformedResponse("TXT string overruns record"));
+                    };
+                    strings.push(String::from_utf8_lossy(string).into_owned());
+                    data = &rest[length as usize..];
+                }
+                RecordData::Txt(strings)
+            }
+            RecordType::Aaaa => {
+                let mut octets = [0u8; 16];
+                if data_length != octets.len() {
+                    return Err(DnsError::MalformedResponse("AAAA record is not 16 bytes"));
+                }
+                reader
+                    .read_exact(&mut octets)
+                    .map_err(|_| DnsError::ResponseTooShort)?;
+                RecordData::Aaaa(Ipv6Addr::from(octets))
+            }
+            RecordType::Other(_) => {
+                RecordData::Other(reader.get_ref()[data_start..data_end].to_vec())
+            }
+        };
+
+        // Names inside the data may be compressed, so always continue from the declared length
+        reader.set_position(data_end as u64);
+
+        records.push(Record {
+            name,
+            record_type,
+            class,
+            ttl,
+            data,
+        });
+    }
+    Ok(records)
+}
+
+/// Reads a (possibly compressed) domain name, leaving the reader just past the name as it
+/// appears at the current position.
+fn read_name(reader: &mut Cursor<&[u8]>) -> DnsResult<String> {
+    let packet = *reader.get_ref();
+    let mut pos = reader.position() as usize;
+    let mut resume_at = None;
+    let mut jumps = 0;
+    let mut name = String::new();
+
+    loop {
+        let Some(&length) = packet.get(pos) else {
+            return Err(DnsError::ResponseTooShort);
+        };
+        match length & 0xC0 {
+            0x00 if length == 0 => {
+                pos += 1;
+                break;
+            }
+            0x00 => {
+                let label_start = pos + 1;
+                let label_end = label_start + length as usize;
+                let Some(label) = packet.get(label_start..label_end) else {
+                    return Err(DnsError::ResponseTooShort);
+                };
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                if name.len() > MAX_NAME_LENGTH {
+                    return Err(DnsError::MalformedResponse("name too long"));
+                }
+                pos = label_end;
+            }
+            0xC0 => {
+                let Some(&low) = packet.get(pos + 1) else {
+                    return Err(DnsError::ResponseTooShort);
+                };
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return Err(DnsError::MalformedResponse("too many compression pointers"));
+                }
+                if resume_at.is_none() {
+                    resume_at = Some(pos + 2);
+                }
+                pos = (((length & 0x3F) as usize) << 8) | low as usize;
+            }
+            _ => return Err(DnsError::MalformedResponse("unknown label type")),
+        }
+    }
+
+    reader.set_position(resume_at.unwrap_or(pos) as u64);
+    Ok(name)
+}
+
+fn read_u16(reader: &mut Cursor<&[u8]>) -> DnsResult<u16> {
+    reader
+        .read_u16::<BigEndian>()
+        .map_err(|_| DnsError::ResponseTooShort)
+}
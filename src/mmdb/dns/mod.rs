@@ -0,0 +1,831 @@
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+pub use config::ResolverConfig;
+use message::{Message, Record, RecordData, RecordType};
+pub use resolver::Resolver;
+pub use transport::Transport;
+
+#[cfg(target_os = "windows")]
+mod adapters;
+pub mod cache;
+pub mod config;
+pub mod hosts;
+pub mod https;
+pub mod idna;
+pub mod message;
+pub mod resolver;
+pub mod tls;
+pub mod transport;
+
+#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum DnsError {
+    #[error("Failed to bind UDP socket: {0}")]
+    BindFailed(#[source] std::io::Error),
+    #[error("Failed to connect to DNS server: {0}")]
+    ConnectFailed(#[source] std::io::Error),
+    #[error("Failed to write packet: {0}")]
+    WriteFailed(#[source] std::io::Error),
+    #[error("Failed to send DNS query: {0}")]
+    SendFailed(#[source] std::io::Error),
+    #[error("Failed to receive DNS response: {0}")]
+    RecvFailed(#[source] std::io::Error),
+    #[error("Domain part too long")]
+    DomainPartTooLong,
+    #[error("Domain name '{0}' is longer than 253 bytes")]
+    NameTooLong(String),
+    #[error("Domain name '{0}' contains an empty label")]
+    EmptyLabel(String),
+    #[error("Domain name '{0}' can't be converted to ASCII")]
+    InvalidName(String),
+    #[error("DNS response too short")]
+    ResponseTooShort,
+    #[error("DNS response is malformed: {0}")]
+    MalformedResponse(&'static str),
+    #[error("DNS error with code: {0}")]
+    DnsErrorCode(u8),
+    #[error("No DNS record found for domain '{0}'")]
+    NoRecordFound(String),
+    #[error("No DNS servers are configured")]
+    NoServers,
+    #[error("DNS over TLS failed: {0}")]
+    TlsFailed(String),
+    #[error("DNS over HTTPS failed: {0}")]
+    HttpsFailed(String),
+    #[error("Timed out waiting for a DNS response")]
+    Timeout,
+}
+
+pub type DnsResult<T> = Result<T, DnsError>;
+
+impl DnsError {
+    /// Whether the name doesn't exist or has no records of the queried type, as opposed to
+    /// the lookup failing
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            DnsError::DnsErrorCode(RCODE_NAME_ERROR) | DnsError::NoRecordFound(_)
+        )
+    }
+}
+
+const RCODE_FORMAT_ERROR: u8 = 1;
+const RCODE_NAME_ERROR: u8 = 3;
+
+// Bounds the extra queries made when an answer ends in a CNAME whose target wasn't included
+const MAX_CNAME_QUERIES: usize = 8;
+// Servers don't always keep to the payload size advertised with EDNS, so receive any datagram
+// whole rather than have it cut short and fail to parse
+const MAX_UDP_RESPONSE_SIZE: usize = 65535;
+
+#[derive(Clone, Debug)]
+pub struct AddressRecord {
+    pub ip: IpAddr,
+    pub ttl: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// A records only
+    Ipv4,
+    /// AAAA records only
+    Ipv6,
+    /// Both A and AAAA records, IPv4 addresses first
+    Both,
+}
+
+impl AddressFamily {
+    fn record_types(self) -> &'static [RecordType] {
+        match self {
+            AddressFamily::Ipv4 => &[RecordType::A],
+            AddressFamily::Ipv6 => &[RecordType::Aaaa],
+            AddressFamily::Both => &[RecordType::A, RecordType::Aaaa],
+        }
+    }
+}
+
+pub fn query_dns_for_domain(config: &ResolverConfig, domain: &str) -> DnsResult<IpAddr> {
+    Ok(resolve(config, domain, AddressFamily::Both)?[0].ip)
+}
+
+/// Resolves every address of the given family for `domain`, following CNAME chains.
+///
+/// Names listed in the hosts file are answered from it without querying any server.
+/// Internationalized names are converted to their punycode form first.
+///
+/// In [`AddressFamily::Both`] mode, a failure for one family is ignored as long as the
+/// other one resolves.
+pub fn resolve(
+    config: &ResolverConfig,
+    domain: &str,
+    family: AddressFamily,
+) -> DnsResult<Vec<AddressRecord>> {
+    let domain = &idna::to_ascii(domain)?;
+    if let Some(addresses) = hosts_file_addresses(config, domain, family) {
+        return Ok(addresses);
+    }
+
+    let mut addresses = FamilyAddresses::default();
+    for &record_type in family.record_types() {
+        addresses.add(resolve_records(config, domain, record_type));
+    }
+    addresses.finish()
+}
+
+/// Looks up the records of `record_type` owned by `domain` (eg. its MX or TXT records),
+/// following CNAME chains. Fails with [`DnsError::NoRecordFound`] if it has none.
+pub fn lookup(
+    config: &ResolverConfig,
+    domain: &str,
+    record_type: RecordType,
+) -> DnsResult<Vec<Record>> {
+    resolve_records(config, &idna::to_ascii(domain)?, record_type)
+}
+
+/// Queries the records of `record_type` owned by `domain`, following CNAME chains.
+fn resolve_records(
+    config: &ResolverConfig,
+    domain: &str,
+    record_type: RecordType,
+) -> DnsResult<Vec<Record>> {
+    let mut name = domain.to_string();
+    for _ in 0..MAX_CNAME_QUERIES {
+        let message = query(config, &name, record_type)?;
+        match chain_step(&message, &name, record_type) {
+            ChainStep::Found(records) => return Ok(records),
+            ChainStep::Follow(target) => name = target,
+            ChainStep::End => break,
+        }
+    }
+    Err(DnsError::NoRecordFound(domain.to_string()))
+}
+
+/// Looks up the hostname of `ip` through its PTR record, returning `None` if it has none.
+pub fn reverse_lookup(config: &ResolverConfig, ip: IpAddr) -> DnsResult<Option<String>> {
+    ptr_name(resolve_records(config, &reverse_name(ip), RecordType::Ptr))
+}
+
+/// The name PTR records for `ip` live under, eg. `1.1.1.1.in-addr.arpa` for `1.1.1.1`, or
+/// one label per nibble under `ip6.arpa` for IPv6 addresses.
+pub fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(ip) => {
+            let mut name = String::with_capacity(72);
+            for byte in ip.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0F, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// Sends a query to each configured server in turn, returning the first usable response.
+///
+/// A server that can't be reached or fails to answer is skipped, but a definitive answer
+/// (including "no such domain") is returned as is. The list of servers is gone through up to
+/// `attempts` times.
+pub fn query(config: &ResolverConfig, name: &str, record_type: RecordType) -> DnsResult<Message> {
+    if let Some(message) = config.cache.as_ref().and_then(|c| c.get(name, record_type)) {
+        return check_rcode(message);
+    }
+
+    let mut last_error = DnsError::NoServers;
+    for _ in 0..config.attempts.max(1) {
+        for server in &config.servers {
+            let result = server.exchange(name, record_type, config.timeout);
+            if let (Ok(message), Some(cache)) = (&result, &config.cache) {
+                cache.insert(name, record_type, message);
+            }
+            match result.and_then(check_rcode) {
+                Err(err) if should_try_next_server(&err) => last_error = err,
+                result => return result,
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Sends a single query to `server` and waits up to `timeout` for the matching response.
+///
+/// The query goes over UDP first, and is repeated over TCP if the response was truncated.
+pub fn query_server(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    check_rcode(exchange(server, name, record_type, timeout)?)
+}
+
+// Gets the response from `server`, whatever its rcode
+fn exchange(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    let mut message = udp_exchange(server, name, record_type, timeout, true)?;
+    if message.rcode() == RCODE_FORMAT_ERROR {
+        // Servers that predate EDNS can reject queries carrying an OPT record
+        message = udp_exchange(server, name, record_type, timeout, false)?;
+    }
+    if message.is_truncated() {
+        message = tcp_exchange(server, name, record_type, timeout)?;
+    }
+    Ok(message)
+}
+
+fn udp_exchange(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+    edns: bool,
+) -> DnsResult<Message> {
+    let udp =
+        std::net::UdpSocket::bind(unspecified_address(server)).map_err(DnsError::BindFailed)?;
+    udp.connect(server).map_err(DnsError::ConnectFailed)?;
+
+    let id = random_id();
+    let packet = message::build_query(id, name, record_type, edns)?;
+
+    udp.send(&packet).map_err(DnsError::SendFailed)?;
+    let mut resp = vec![0; MAX_UDP_RESPONSE_SIZE];
+
+    // A timeout too long to add to the current time is as good as none
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(DnsError::Timeout);
+        }
+        udp.set_read_timeout(remaining)
+            .map_err(DnsError::RecvFailed)?;
+        let length = udp
+            .recv(&mut resp)
+            .map_err(|err| io_error(err, DnsError::RecvFailed))?;
+        if let Some(message) = matching_response(&resp[..length], id, name, record_type)? {
+            return Ok(message);
+        }
+    }
+}
+
+fn tcp_exchange(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    let mut tcp = std::net::TcpStream::connect_timeout(&server, timeout)
+        .map_err(|err| io_error(err, DnsError::ConnectFailed))?;
+    tcp.set_read_timeout(Some(timeout))
+        .map_err(DnsError::RecvFailed)?;
+    tcp.set_write_timeout(Some(timeout))
+        .map_err(DnsError::SendFailed)?;
+    stream_exchange(&mut tcp, name, record_type)
+}
+
+/// Sends a query over a connected stream with the 2 byte length prefix used by TCP and TLS
+fn stream_exchange(
+    stream: &mut (impl std::io::Read + std::io::Write),
+    name: &str,
+    record_type: RecordType,
+) -> DnsResult<Message> {
+    let id = random_id();
+    let packet = frame_tcp(message::build_query(id, name, record_type, true)?)?;
+    stream
+        .write_all(&packet)
+        .map_err(|err| io_error(err, DnsError::SendFailed))?;
+
+    let mut length = [0u8; 2];
+    stream
+        .read_exact(&mut length)
+        .map_err(|err| io_error(err, DnsError::RecvFailed))?;
+    let mut resp = vec![0; u16::from_be_bytes(length) as usize];
+    stream
+        .read_exact(&mut resp)
+        .map_err(|err| io_error(err, DnsError::RecvFailed))?;
+
+    matching_response(&resp, id, name, record_type)?.ok_or(DnsError::MalformedResponse(
+        "TCP response does not match the query",
+    ))
+}
+
+/// Same as [`query_dns_for_domain`], but over a tokio socket so it can be awaited from async tasks.
+#[cfg(feature = "async")]
+pub async fn query_dns_for_domain_async(
+    config: &ResolverConfig,
+    domain: &str,
+) -> DnsResult<IpAddr> {
+    Ok(resolve_async(config, domain, AddressFamily::Both).await?[0].ip)
+}
+
+#[cfg(feature = "async")]
+pub async fn resolve_async(
+    config: &ResolverConfig,
+    domain: &str,
+    family: AddressFamily,
+) -> DnsResult<Vec<AddressRecord>> {
+    let domain = &idna::to_ascii(domain)?;
+    if let Some(addresses) = hosts_file_addresses(config, domain, family) {
+        return Ok(addresses);
+    }
+
+    let mut addresses = FamilyAddresses::default();
+    for &record_type in family.record_types() {
+        addresses.add(resolve_records_async(config, domain, record_type).await);
+    }
+    addresses.finish()
+}
+
+#[cfg(feature = "async")]
+pub async fn lookup_async(
+    config: &ResolverConfig,
+    domain: &str,
+    record_type: RecordType,
+) -> DnsResult<Vec<Record>> {
+    resolve_records_async(config, &idna::to_ascii(domain)?, record_type).await
+}
+
+#[cfg(feature = "async")]
+async fn resolve_records_async(
+    config: &ResolverConfig,
+    domain: &str,
+    record_type: RecordType,
+) -> DnsResult<Vec<Record>> {
+    let mut name = domain.to_string();
+    for _ in 0..MAX_CNAME_QUERIES {
+        let message = query_async(config, &name, record_type).await?;
+        match chain_step(&message, &name, record_type) {
+            ChainStep::Found(records) => return Ok(records),
+            ChainStep::Follow(target) => name = target,
+            ChainStep::End => break,
+        }
+    }
+    Err(DnsError::NoRecordFound(domain.to_string()))
+}
+
+#[cfg(feature = "async")]
+pub async fn reverse_lookup_async(
+    config: &ResolverConfig,
+    ip: IpAddr,
+) -> DnsResult<Option<String>> {
+    ptr_name(resolve_records_async(config, &reverse_name(ip), RecordType::Ptr).await)
+}
+
+#[cfg(feature = "async")]
+pub async fn query_async(
+    config: &ResolverConfig,
+    name: &str,
+    record_type: RecordType,
+) -> DnsResult<Message> {
+    if let Some(message) = config.cache.as_ref().and_then(|c| c.get(name, record_type)) {
+        return check_rcode(message);
+    }
+
+    let mut last_error = DnsError::NoServers;
+    for _ in 0..config.attempts.max(1) {
+        for server in &config.servers {
+            let result = match server.udp_address() {
+                Some(address) => exchange_async(address, name, record_type, config.timeout).await,
+                None => exchange_blocking(server.clone(), name, record_type, config.timeout).await,
+            };
+            if let (Ok(message), Some(cache)) = (&result, &config.cache) {
+                cache.insert(name, record_type, message);
+            }
+            match result.and_then(check_rcode) {
+                Err(err) if should_try_next_server(&err) => last_error = err,
+                result => return result,
+            }
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(feature = "async")]
+pub async fn query_server_async(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    check_rcode(exchange_async(server, name, record_type, timeout).await?)
+}
+
+#[cfg(feature = "async")]
+async fn exchange_async(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    let mut message = udp_exchange_async(server, name, record_type, timeout, true).await?;
+    if message.rcode() == RCODE_FORMAT_ERROR {
+        message = udp_exchange_async(server, name, record_type, timeout, false).await?;
+    }
+    if message.is_truncated() {
+        message = tcp_exchange_async(server, name, record_type, timeout).await?;
+    }
+    Ok(message)
+}
+
+#[cfg(feature = "async")]
+async fn udp_exchange_async(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+    edns: bool,
+) -> DnsResult<Message> {
+    let udp = tokio::net::UdpSocket::bind(unspecified_address(server))
+        .await
+        .map_err(DnsError::BindFailed)?;
+    udp.connect(server).await.map_err(DnsError::ConnectFailed)?;
+
+    let id = random_id();
+    let packet = message::build_query(id, name, record_type, edns)?;
+
+    udp.send(&packet).await.map_err(DnsError::SendFailed)?;
+    let mut resp = vec![0; MAX_UDP_RESPONSE_SIZE];
+
+    // A timeout too long to add to the current time is as good as none
+    let deadline = tokio::time::Instant::now().checked_add(timeout);
+    loop {
+        let recv = udp.recv(&mut resp);
+        let length = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, recv).await {
+                Ok(result) => result.map_err(DnsError::RecvFailed)?,
+                Err(_) => return Err(DnsError::Timeout),
+            },
+            None => recv.await.map_err(DnsError::RecvFailed)?,
+        };
+        if let Some(message) = matching_response(&resp[..length], id, name, record_type)? {
+            return Ok(message);
+        }
+    }
+}
+
+// Transports without an async implementation run on tokio's blocking thread pool
+#[cfg(feature = "async")]
+async fn exchange_blocking(
+    server: std::sync::Arc<dyn Transport>,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    let name = name.to_string();
+    tokio::task::spawn_blocking(move || server.exchange(&name, record_type, timeout))
+        .await
+        .map_err(|err| DnsError::RecvFailed(std::io::Error::other(err)))?
+}
+
+#[cfg(feature = "async")]
+async fn tcp_exchange_async(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> DnsResult<Message> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let exchange = async {
+        let mut tcp = tokio::net::TcpStream::connect(server)
+            .await
+            .map_err(DnsError::ConnectFailed)?;
+
+        let id = random_id();
+        let packet = frame_tcp(message::build_query(id, name, record_type, true)?)?;
+        tcp.write_all(&packet).await.map_err(DnsError::SendFailed)?;
+
+        let length = tcp.read_u16().await.map_err(DnsError::RecvFailed)?;
+        let mut resp = vec![0; length as usize];
+        tcp.read_exact(&mut resp)
+            .await
+            .map_err(DnsError::RecvFailed)?;
+
+        matching_response(&resp, id, name, record_type)?.ok_or(DnsError::MalformedResponse(
+            "TCP response does not match the query",
+        ))
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| DnsError::Timeout)?
+}
+
+// Local address to bind to, in the same family as the server
+fn unspecified_address(server: SocketAddr) -> SocketAddr {
+    match server {
+        SocketAddr::V4(_) => (std::net::Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// Prefixes a packet with its length, as messages are framed over TCP
+fn frame_tcp(packet: Vec<u8>) -> DnsResult<Vec<u8>> {
+    let Ok(length) = u16::try_from(packet.len()) else {
+        return Err(DnsError::WriteFailed(std::io::Error::other(
+            "query too long for TCP",
+        )));
+    };
+    let mut framed = Vec::with_capacity(packet.len() + 2);
+    framed.extend_from_slice(&length.to_be_bytes());
+    framed.extend_from_slice(&packet);
+    Ok(framed)
+}
+
+// Socket timeouts surface as WouldBlock on unix and TimedOut on Windows
+fn io_error(err: std::io::Error, wrap: fn(std::io::Error) -> DnsError) -> DnsError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => DnsError::Timeout,
+        _ => wrap(err),
+    }
+}
+
+fn should_try_next_server(err: &DnsError) -> bool {
+    match err {
+        // SERVFAIL, NOTIMP and REFUSED only say something about this server
+        DnsError::DnsErrorCode(rcode) => matches!(rcode, 2 | 4 | 5),
+        DnsError::DomainPartTooLong
+        | DnsError::NameTooLong(_)
+        | DnsError::EmptyLabel(_)
+        | DnsError::InvalidName(_)
+        | DnsError::WriteFailed(_) => false,
+        _ => true,
+    }
+}
+
+/// Transaction IDs only need to be unpredictable to off-path attackers, so the randomly keyed
+/// hasher from std is enough without pulling in an RNG crate.
+fn random_id() -> u16 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish() as u16
+}
+
+/// Parses `resp` if it is the response to the query that was sent, returning `None` for stray
+/// or spoofed packets, which should be ignored while waiting for the real response.
+fn matching_response(
+    resp: &[u8],
+    id: u16,
+    name: &str,
+    record_type: RecordType,
+) -> DnsResult<Option<Message>> {
+    if resp.get(..2) != Some(&id.to_be_bytes()[..]) {
+        return Ok(None);
+    }
+    let message = Message::parse(resp)?;
+    Ok(message.is_response_to(name, record_type).then_some(message))
+}
+
+fn check_rcode(message: Message) -> DnsResult<Message> {
+    match message.rcode() {
+        0 => Ok(message),
+        rcode => Err(DnsError::DnsErrorCode(rcode)),
+    }
+}
+
+fn hosts_file_addresses(
+    config: &ResolverConfig,
+    domain: &str,
+    family: AddressFamily,
+) -> Option<Vec<AddressRecord>> {
+    let ips = hosts::lookup(config.hosts_path.as_ref()?, domain, family);
+    if ips.is_empty() {
+        return None;
+    }
+    // Hosts file entries have no TTL, and are read fresh on every lookup
+    Some(
+        ips.into_iter()
+            .map(|ip| AddressRecord { ip, ttl: 0 })
+            .collect(),
+    )
+}
+
+/// Where a response to the query for `name` leaves a CNAME chain: at the records that were
+/// asked for, at a name whose records need querying next, or at a dead end
+enum ChainStep {
+    Found(Vec<Record>),
+    Follow(String),
+    End,
+}
+
+fn chain_step(message: &Message, name: &str, record_type: RecordType) -> ChainStep {
+    let (records, target) = message.follow_cnames(name, record_type);
+    if !records.is_empty() {
+        ChainStep::Found(records.into_iter().cloned().collect())
+    } else if target.eq_ignore_ascii_case(name.trim_end_matches('.')) {
+        ChainStep::End
+    } else {
+        ChainStep::Follow(target)
+    }
+}
+
+/// The addresses of a name, gathered from a lookup for each record type of its family
+#[derive(Default)]
+struct FamilyAddresses {
+    addresses: Vec<AddressRecord>,
+    first_error: Option<DnsError>,
+}
+
+impl FamilyAddresses {
+    fn add(&mut self, lookup: DnsResult<Vec<Record>>) {
+        match lookup {
+            Ok(records) => self.addresses.extend(address_records(&records)),
+            Err(err) => {
+                self.first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    // A failure for one family is ignored as long as the other one resolved
+    fn finish(self) -> DnsResult<Vec<AddressRecord>> {
+        match self.first_error {
+            Some(err) if self.addresses.is_empty() => Err(err),
+            _ => Ok(self.addresses),
+        }
+    }
+}
+
+// The name from a PTR lookup, an address without one isn't an error
+fn ptr_name(lookup: DnsResult<Vec<Record>>) -> DnsResult<Option<String>> {
+    match lookup {
+        Ok(records) => Ok(records.iter().find_map(|record| match &record.data {
+            RecordData::Ptr(name) => Some(name.clone()),
+            _ => None,
+        })),
+        Err(err) if err.is_not_found() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn address_records(records: &[Record]) -> Vec<AddressRecord> {
+    records
+        .iter()
+        .filter_map(|record| match record.data {
+            RecordData::A(ip) => Some(AddressRecord {
+                ip: IpAddr::V4(ip),
+                ttl: record.ttl,
+            }),
+            RecordData::Aaaa(ip) => Some(AddressRecord {
+                ip: IpAddr::V6(ip),
+                ttl: record.ttl,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, UdpSocket},
+        sync::{Arc, mpsc},
+        thread,
+    };
+
+    use super::{transport::UdpTransport, *};
+    use message::Question;
+
+    pub(super) const NAME: &str = "example.com";
+    pub(super) const ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+
+    /// A DNS server on 127.0.0.1 that answers each query with the packets `respond` makes
+    /// from it, reporting every query it gets on the returned channel
+    fn stand_in(
+        respond: impl Fn(&Message) -> Vec<Vec<u8>> + Send + 'static,
+    ) -> (SocketAddr, mpsc::Receiver<Message>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut packet = vec![0; MAX_UDP_RESPONSE_SIZE];
+            while let Ok((length, from)) = socket.recv_from(&mut packet) {
+                let query = Message::parse(&packet[..length]).unwrap();
+                for response in respond(&query) {
+                    socket.send_to(&response, from).unwrap();
+                }
+                if tx.send(query).is_err() {
+                    return;
+                }
+            }
+        });
+        (address, rx)
+    }
+
+    /// The response to `query` answering its question with `address`
+    pub(super) fn answer(query: &Message, address: Ipv4Addr) -> Message {
+        Message {
+            id: query.id,
+            flags: 0x8000,
+            questions: query.questions.clone(),
+            answers: vec![Record {
+                name: query.questions[0].name.clone(),
+                record_type: RecordType::A,
+                class: 1,
+                ttl: 300,
+                data: RecordData::A(address),
+            }],
+            authorities: vec![],
+            additionals: vec![],
+        }
+    }
+
+    fn config(servers: &[SocketAddr]) -> ResolverConfig {
+        let servers = servers
+            .iter()
+            .map(|&server| Arc::new(UdpTransport::new(server)) as Arc<dyn Transport>)
+            .collect();
+        let mut config = ResolverConfig::with_servers(servers);
+        config.timeout = Duration::from_millis(200);
+        config.attempts = 1;
+        config.cache = None;
+        config.hosts_path = None;
+        config
+    }
+
+    pub(super) fn answered_address(message: &Message) -> Option<Ipv4Addr> {
+        match message.answers.as_slice() {
+            [
+                Record {
+                    data: RecordData::A(address),
+                    ..
+                },
+            ] => Some(*address),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn no_reply_times_out() {
+        let (server, queries) = stand_in(|_| vec![]);
+        let result = query(&config(&[server]), NAME, RecordType::A);
+        assert!(matches!(result, Err(DnsError::Timeout)), "{result:?}");
+        assert!(queries.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn reply_with_wrong_id_is_ignored() {
+        let (server, _queries) = stand_in(|query| {
+            let mut spoofed = answer(query, Ipv4Addr::new(203, 0, 113, 1));
+            spoofed.id = query.id.wrapping_add(1);
+            vec![
+                spoofed.to_bytes().unwrap(),
+                answer(query, ADDRESS).to_bytes().unwrap(),
+            ]
+        });
+        let message = query(&config(&[server]), NAME, RecordType::A).unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+    }
+
+    #[test]
+    fn reply_to_another_question_is_ignored() {
+        let (server, _queries) = stand_in(|query| {
+            let mut spoofed = answer(query, Ipv4Addr::new(203, 0, 113, 1));
+            spoofed.questions = vec![Question {
+                name: "example.org".to_string(),
+                record_type: RecordType::A,
+                class: 1,
+            }];
+            vec![
+                spoofed.to_bytes().unwrap(),
+                answer(query, ADDRESS).to_bytes().unwrap(),
+            ]
+        });
+        let message = query(&config(&[server]), NAME, RecordType::A).unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+    }
+
+    #[test]
+    fn timeout_too_long_for_a_deadline_waits_for_the_reply() {
+        let (server, _queries) = stand_in(|query| vec![answer(query, ADDRESS).to_bytes().unwrap()]);
+        let mut config = config(&[server]);
+        config.timeout = Duration::MAX;
+        let message = query(&config, NAME, RecordType::A).unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+    }
+
+    #[test]
+    fn silent_server_is_skipped_for_the_next() {
+        let (silent, silent_queries) = stand_in(|_| vec![]);
+        let (server, queries) = stand_in(|query| vec![answer(query, ADDRESS).to_bytes().unwrap()]);
+        let message = query(&config(&[silent, server]), NAME, RecordType::A).unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+        assert!(silent_queries.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert!(queries.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+}
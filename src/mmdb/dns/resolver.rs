@@ -0,0 +1,112 @@
+use std::{collections::HashMap, fmt, net::IpAddr};
+
+use super::{
+    AddressFamily, AddressRecord, DnsError, DnsResult, ResolverConfig,
+    message::{Record, RecordType},
+};
+
+/// Turns domains into addresses, and addresses back into hostnames.
+///
+/// [`Mmdb`](super::super::Mmdb) resolves domains through one of these, so lookups can be routed
+/// through another resolver or answered from a fixed table in tests. [`ResolverConfig`] is the
+/// built-in one, querying DNS servers directly.
+pub trait Resolver: fmt::Debug + Send + Sync {
+    /// Every address of the given family for `domain`, IPv4 addresses first.
+    fn resolve(&self, domain: &str, family: AddressFamily) -> DnsResult<Vec<AddressRecord>>;
+
+    /// The hostname of `ip`, or `None` if it has none.
+    fn reverse_lookup(&self, ip: IpAddr) -> DnsResult<Option<String>>;
+
+    /// The records of `record_type` owned by `domain`, such as its MX or TXT records.
+    ///
+    /// Resolvers that only deal in addresses have none.
+    fn lookup(&self, domain: &str, _record_type: RecordType) -> DnsResult<Vec<Record>> {
+        Err(DnsError::NoRecordFound(domain.to_string()))
+    }
+
+    /// The built-in resolver's settings, so async lookups can query its servers over tokio
+    /// sockets rather than on a blocking thread.
+    fn config(&self) -> Option<&ResolverConfig> {
+        None
+    }
+}
+
+impl Resolver for ResolverConfig {
+    fn resolve(&self, domain: &str, family: AddressFamily) -> DnsResult<Vec<AddressRecord>> {
+        super::resolve(self, domain, family)
+    }
+
+    fn reverse_lookup(&self, ip: IpAddr) -> DnsResult<Option<String>> {
+        super::reverse_lookup(self, ip)
+    }
+
+    fn lookup(&self, domain: &str, record_type: RecordType) -> DnsResult<Vec<Record>> {
+        super::lookup(self, domain, record_type)
+    }
+
+    fn config(&self) -> Option<&ResolverConfig> {
+        Some(self)
+    }
+}
+
+/// Answers from a fixed table without touching the network, eg. in tests.
+///
+/// Domains are matched case-insensitively and without a trailing dot. Unknown domains fail with
+/// [`DnsError::NoRecordFound`], unknown addresses have no hostname.
+#[derive(Clone, Debug, Default)]
+pub struct StaticResolver {
+    addresses: HashMap<String, Vec<IpAddr>>,
+    hostnames: HashMap<IpAddr, String>,
+}
+
+impl StaticResolver {
+    pub fn new() -> StaticResolver {
+        StaticResolver::default()
+    }
+
+    /// Adds an address for `domain`, after any it already has
+    pub fn insert(&mut self, domain: &str, ip: IpAddr) {
+        let ips = self.addresses.entry(normalize(domain)).or_default();
+        if !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+
+    /// Sets the hostname returned for `ip` by reverse lookups
+    pub fn insert_reverse(&mut self, ip: IpAddr, hostname: &str) {
+        self.hostnames.insert(ip, hostname.to_string());
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, domain: &str, family: AddressFamily) -> DnsResult<Vec<AddressRecord>> {
+        let mut ips = self
+            .addresses
+            .get(&normalize(domain))
+            .into_iter()
+            .flatten()
+            .filter(|ip| match family {
+                AddressFamily::Ipv4 => ip.is_ipv4(),
+                AddressFamily::Ipv6 => ip.is_ipv6(),
+                AddressFamily::Both => true,
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        if ips.is_empty() {
+            return Err(DnsError::NoRecordFound(domain.to_string()));
+        }
+        ips.sort_by_key(|ip| ip.is_ipv6());
+        Ok(ips
+            .into_iter()
+            .map(|ip| AddressRecord { ip, ttl: 0 })
+            .collect())
+    }
+
+    fn reverse_lookup(&self, ip: IpAddr) -> DnsResult<Option<String>> {
+        Ok(self.hostnames.get(&ip).cloned())
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
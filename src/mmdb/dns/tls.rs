@@ -0,0 +1,140 @@
+use std::{net::SocketAddr, time::Duration};
+
+use super::{
+    DnsError, DnsResult, io_error,
+    message::{Message, RecordType},
+    stream_exchange,
+    transport::Transport,
+};
+use crate::http;
+
+pub const DNS_OVER_TLS_PORT: u16 = 853;
+
+/// DNS over TLS (RFC 7858), with the server's certificate checked against `server_name`
+#[derive(Clone, Debug)]
+pub struct TlsTransport {
+    pub address: SocketAddr,
+    pub server_name: String,
+    /// Trusted on top of the system's root certificates, eg. a local CA (DER encoded)
+    pub extra_roots: Vec<Vec<u8>>,
+}
+
+impl TlsTransport {
+    pub fn new(address: SocketAddr, server_name: impl Into<String>) -> TlsTransport {
+        TlsTransport {
+            address,
+            server_name: server_name.into(),
+            extra_roots: vec![],
+        }
+    }
+}
+
+impl Transport for TlsTransport {
+    fn exchange(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> DnsResult<Message> {
+        let tcp = std::net::TcpStream::connect_timeout(&self.address, timeout)
+            .map_err(|err| io_error(err, DnsError::ConnectFailed))?;
+        tcp.set_read_timeout(Some(timeout))
+            .map_err(DnsError::RecvFailed)?;
+        tcp.set_write_timeout(Some(timeout))
+            .map_err(DnsError::SendFailed)?;
+
+        let connector = http::tls_connector(&self.extra_roots)
+            .map_err(|err| DnsError::TlsFailed(err.to_string()))?;
+        let mut stream = connector
+            .connect(&self.server_name, tcp)
+            .map_err(|err| DnsError::TlsFailed(err.to_string()))?;
+
+        // Same framing as plain DNS over TCP
+        stream_exchange(&mut stream, name, record_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+    use crate::dns::tests::{ADDRESS, NAME, answer, answered_address};
+
+    static CA: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/ca.pem"));
+    static CERTIFICATE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testdata/localhost.pem"
+    ));
+    static KEY: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testdata/localhost.key"
+    ));
+
+    /// A DNS over TLS server on 127.0.0.1, with a certificate for `localhost` issued by the
+    /// test CA, that answers one query
+    fn stand_in() -> SocketAddr {
+        let identity =
+            native_tls::Identity::from_pkcs8(CERTIFICATE.as_bytes(), KEY.as_bytes()).unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            // Clients that don't trust the certificate hang up during the handshake
+            let Ok(mut stream) = acceptor.accept(tcp) else {
+                return;
+            };
+            let mut length = [0; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut query = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let response = answer(&Message::parse(&query).unwrap(), ADDRESS)
+                .to_bytes()
+                .unwrap();
+            stream
+                .write_all(&(response.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&response).unwrap();
+        });
+        address
+    }
+
+    fn transport(server_name: &str, trust_ca: bool) -> TlsTransport {
+        let mut transport = TlsTransport::new(stand_in(), server_name);
+        if trust_ca {
+            let ca = native_tls::Certificate::from_pem(CA.as_bytes()).unwrap();
+            transport.extra_roots.push(ca.to_der().unwrap());
+        }
+        transport
+    }
+
+    #[test]
+    fn answers_with_a_trusted_certificate() {
+        let message = transport("localhost", true)
+            .exchange(NAME, RecordType::A, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(answered_address(&message), Some(ADDRESS));
+    }
+
+    #[test]
+    fn untrusted_certificate_is_rejected() {
+        let result =
+            transport("localhost", false).exchange(NAME, RecordType::A, Duration::from_secs(5));
+        assert!(matches!(result, Err(DnsError::TlsFailed(_))), "{result:?}");
+    }
+
+    #[test]
+    fn certificate_for_another_name_is_rejected() {
+        let result = transport("dns.example.net", true).exchange(
+            NAME,
+            RecordType::A,
+            Duration::from_secs(5),
+        );
+        assert!(matches!(result, Err(DnsError::TlsFailed(_))), "{result:?}");
+    }
+}
@@ -0,0 +1,53 @@
+use std::{fmt, net::SocketAddr, time::Duration};
+
+use super::{
+    DnsResult,
+    message::{Message, RecordType},
+};
+
+/// A way of getting a query to a DNS server and its response back.
+///
+/// [`ResolverConfig`](super::ResolverConfig) holds a list of these, so plain DNS, DNS over
+/// HTTPS and DNS over TLS servers can be mixed, and other transports plugged in.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends a query for `name` and returns the matching response, whatever its rcode.
+    fn exchange(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> DnsResult<Message>;
+
+    /// The server's address if it speaks plain DNS, so async lookups can query it over tokio
+    /// sockets rather than on a blocking thread.
+    fn udp_address(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+/// Plain DNS over UDP, falling back to TCP for truncated responses
+#[derive(Clone, Copy, Debug)]
+pub struct UdpTransport {
+    pub address: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn new(address: SocketAddr) -> UdpTransport {
+        UdpTransport { address }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn exchange(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        timeout: Duration,
+    ) -> DnsResult<Message> {
+        super::exchange(self.address, name, record_type, timeout)
+    }
+
+    fn udp_address(&self) -> Option<SocketAddr> {
+        Some(self.address)
+    }
+}
@@ -0,0 +1,31 @@
+use std::io::Cursor;
+
+use super::{
+    Mmdb, MmdbError,
+    archive::{self, DatabaseReader},
+};
+
+#[cfg(feature = "embedded-db")]
+static EMBEDDED_DB: Option<&[u8]> = Some(include_bytes!(env!("IPINFO_EMBEDDED_DB_PATH")));
+#[cfg(not(feature = "embedded-db"))]
+static EMBEDDED_DB: Option<&[u8]> = None;
+
+/// Whether this build was compiled with a database embedded (see the `embedded-db` feature)
+pub fn is_available() -> bool {
+    EMBEDDED_DB.is_some()
+}
+
+impl Mmdb<DatabaseReader> {
+    /// Opens the database that was embedded at compile time from `IPINFO_EMBEDDED_DB`.
+    pub fn embedded() -> Result<Mmdb<DatabaseReader>, MmdbError> {
+        let Some(contents) = EMBEDDED_DB else {
+            return Err(MmdbError::NoEmbeddedDatabase);
+        };
+        let reader = if archive::is_archive(contents) {
+            DatabaseReader::Memory(Cursor::new(archive::extract_database(contents.to_vec())?))
+        } else {
+            DatabaseReader::Embedded(Cursor::new(contents))
+        };
+        Mmdb::new(reader)
+    }
+}
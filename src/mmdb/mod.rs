@@ -6,12 +6,23 @@ use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     num::TryFromIntError,
     sync::{
+        Arc,
         atomic::{AtomicBool, Ordering},
         mpsc::Sender,
     },
+    time::{Duration, SystemTime},
 };
 
+use dns::message::{Record, RecordData, RecordType, Soa};
+
+pub mod archive;
+pub mod base64;
 pub mod dns;
+pub mod embedded;
+pub mod http;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod reload;
 
 #[derive(Clone, Debug)]
 pub enum QueryProgress {
@@ -40,9 +51,12 @@ fn is_verbose() -> bool {
     VERBOSE.load(Ordering::Relaxed)
 }
 
+#[derive(Clone)]
 pub struct Mmdb<T: Read + Seek> {
     reader: T,
     metadata: MmdbMetadata,
+    // Falls back to the system resolvers when unset
+    resolver: Option<Arc<dyn dns::Resolver>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -63,18 +77,265 @@ pub enum MmdbError {
     NotImplemented(&'static str),
     #[error("DNS error")]
     DnsError(#[from] dns::DnsError),
+    #[error("Archive is malformed")]
+    InvalidArchive(&'static str),
+    #[error("Archive does not contain a .mmdb database")]
+    NoDatabaseInArchive,
+    #[error("No database was embedded into this build")]
+    NoEmbeddedDatabase,
 }
 
 #[derive(Clone, Debug)]
 pub struct MmdbInfo {
     pub data: Option<Type>,
     pub dns_info: Option<DnsInfo>,
+    /// Hostname of the queried IP address, only looked up on request
+    pub reverse_name: Option<String>,
 }
 
 #[derive(Clone, Debug)]
 pub struct DnsInfo {
     pub domain: String,
-    pub resolved_ip: IpAddr,
+    /// Every address the domain resolved to, with the database record for each
+    pub addresses: Vec<AddressInfo>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AddressInfo {
+    pub ip: IpAddr,
+    pub data: Option<Type>,
+}
+
+/// Record types looked up by [`Mmdb::query_domain_records`]
+pub const DOMAIN_RECORD_TYPES: [RecordType; 4] = [
+    RecordType::Mx,
+    RecordType::Ns,
+    RecordType::Txt,
+    RecordType::Soa,
+];
+
+/// A domain's mail servers, name servers, TXT and SOA records
+#[derive(Clone, Debug)]
+pub struct RecordsInfo {
+    pub domain: String,
+    /// Mail servers by preference, then name servers
+    pub hosts: Vec<HostInfo>,
+    pub txt: Vec<String>,
+    pub soa: Option<Soa>,
+}
+
+/// A mail or name server, with the database record of each of its addresses
+#[derive(Clone, Debug)]
+pub struct HostInfo {
+    /// [`RecordType::Mx`] or [`RecordType::Ns`]
+    pub record_type: RecordType,
+    pub name: String,
+    /// Preference of a mail server, lower is preferred
+    pub preference: Option<u16>,
+    pub addresses: Vec<AddressInfo>,
+}
+
+impl AddressInfo {
+    /// Pairs up addresses with their records, as returned by [`Mmdb::query_ips`]
+    pub fn zip(ips: Vec<IpAddr>, data: Vec<Option<Type>>) -> Vec<AddressInfo> {
+        ips.into_iter()
+            .zip(data)
+            .map(|(ip, data)| AddressInfo { ip, data })
+            .collect()
+    }
+}
+
+impl MmdbInfo {
+    /// Pairs up the addresses a domain resolved to with their records, as returned by
+    /// [`Mmdb::query_ips`] for those addresses.
+    pub fn for_domain(domain: &str, ips: Vec<IpAddr>, data: Vec<Option<Type>>) -> MmdbInfo {
+        let addresses = AddressInfo::zip(ips, data);
+        MmdbInfo {
+            data: addresses.first().and_then(|address| address.data.clone()),
+            dns_info: Some(DnsInfo {
+                domain: domain.to_string(),
+                addresses,
+            }),
+            reverse_name: None,
+        }
+    }
+}
+
+impl DnsInfo {
+    /// Groups the resolved addresses by record, in the order each record was first seen.
+    pub fn summary(&self) -> Vec<(Option<&Type>, Vec<IpAddr>)> {
+        let mut groups: Vec<(Option<&Type>, Vec<IpAddr>)> = vec![];
+        for address in &self.addresses {
+            let data = address.data.as_ref();
+            match groups.iter_mut().find(|(group, _)| *group == data) {
+                Some((_, ips)) => ips.push(address.ip),
+                None => groups.push((data, vec![address.ip])),
+            }
+        }
+        groups
+    }
+}
+
+/// The records of a domain, gathered from a lookup for each of [`DOMAIN_RECORD_TYPES`]
+#[derive(Default)]
+struct DomainRecords(Vec<Record>);
+
+impl DomainRecords {
+    /// Adds the result of one lookup. A domain without records of that type is fine, any other
+    /// failure fails the whole query.
+    fn add(&mut self, lookup: dns::DnsResult<Vec<Record>>) -> Result<(), MmdbError> {
+        match lookup {
+            Ok(found) => self.0.extend(found),
+            Err(err) if err.is_not_found() => {}
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+
+    fn finish(self, domain: &str) -> Result<RecordsInfo, MmdbError> {
+        if self.0.is_empty() {
+            return Err(dns::DnsError::NoRecordFound(domain.to_string()).into());
+        }
+        Ok(RecordsInfo::from_records(domain, self.0))
+    }
+}
+
+impl RecordsInfo {
+    /// Sorts the records found for `domain` into hosts, TXT strings and the SOA. Hosts are
+    /// left without addresses, for the caller to resolve.
+    pub fn from_records(domain: &str, records: Vec<Record>) -> RecordsInfo {
+        let mut info = RecordsInfo {
+            domain: domain.to_string(),
+            hosts: vec![],
+            txt: vec![],
+            soa: None,
+        };
+        for record in records {
+            let (preference, name) = match record.data {
+                RecordData::Mx {
+                    preference,
+                    exchange,
+                } => (Some(preference), exchange),
+                RecordData::Ns(name) => (None, name),
+                // A TXT record split into several strings is one value
+                RecordData::Txt(strings) => {
+                    info.txt.push(strings.concat());
+                    continue;
+                }
+                RecordData::Soa(soa) => {
+                    info.soa = Some(soa);
+                    continue;
+                }
+                _ => continue,
+            };
+            // A domain without mail servers says so with a "null MX" pointing at the root
+            if name.is_empty() {
+                continue;
+            }
+            info.hosts.push(HostInfo {
+                record_type: record.record_type,
+                name,
+                preference,
+                addresses: vec![],
+            });
+        }
+        info.hosts.sort_by_key(|host| {
+            (
+                host.record_type != RecordType::Mx,
+                host.preference,
+                host.name.to_ascii_lowercase(),
+            )
+        });
+        info
+    }
+}
+
+impl Display for RecordsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DNS: Records for domain '{}'", self.domain)?;
+        if let Some(soa) = &self.soa {
+            write!(
+                f,
+                "\nSOA {} {} (serial {})",
+                soa.mname, soa.rname, soa.serial
+            )?;
+        }
+        for txt in &self.txt {
+            write!(f, "\nTXT {txt:?}")?;
+        }
+
+        for host in &self.hosts {
+            write!(f, "\n\n{}", host.record_type)?;
+            if let Some(preference) = host.preference {
+                write!(f, " {preference}")?;
+            }
+            write!(f, " {}", host.name)?;
+            if host.addresses.is_empty() {
+                write!(f, "\n  No addresses found")?;
+            }
+            for address in &host.addresses {
+                match &address.data {
+                    Some(typ) => write!(f, "\n  {} -> {}", address.ip, typ.short_description())?,
+                    None => write!(f, "\n  {} -> No data found", address.ip)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for MmdbInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(dns_info) = &self.dns_info else {
+            if let Some(reverse_name) = &self.reverse_name {
+                write!(f, "Reverse DNS: {reverse_name}\n\n")?;
+            }
+            return match &self.data {
+                Some(typ) => write!(f, "{typ}"),
+                None => write!(f, "No data found"),
+            };
+        };
+
+        let ips = dns_info
+            .addresses
+            .iter()
+            .map(|address| address.ip.to_string())
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "DNS: Resolved domain '{}' -> {}",
+            dns_info.domain,
+            ips.join(", ")
+        )?;
+
+        for address in &dns_info.addresses {
+            write!(f, "\n\n[{}]\n", address.ip)?;
+            match &address.data {
+                Some(typ) => write!(f, "{typ}")?,
+                None => write!(f, "No data found")?,
+            }
+        }
+
+        if dns_info.addresses.len() > 1 {
+            let summary = dns_info.summary();
+            write!(
+                f,
+                "\n\nSummary: {} addresses in {} distinct records",
+                dns_info.addresses.len(),
+                summary.len()
+            )?;
+            for (typ, ips) in summary {
+                let ips = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+                match typ {
+                    Some(typ) => {
+                        write!(f, "\n  {} -> {}", typ.short_description(), ips.join(", "))?
+                    }
+                    None => write!(f, "\n  No data -> {}", ips.join(", "))?,
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<T: Read + Seek> Mmdb<T> {
@@ -162,7 +423,72 @@ impl<T: Read + Seek> Mmdb<T> {
 
         let metadata = MmdbMetadata::new(&typ)?;
         reader.seek(SeekFrom::Start(0))?;
-        Ok(Self { reader, metadata })
+        Ok(Self {
+            reader,
+            metadata,
+            resolver: None,
+        })
+    }
+
+    /// Sanity checks the search tree against the file, beyond the metadata that `new` reads.
+    ///
+    /// Useful before trusting a database that may have been partially written or truncated.
+    pub fn validate(&mut self) -> Result<(), MmdbError> {
+        let file_size = self.reader.seek(SeekFrom::End(0))?;
+        let search_tree_size =
+            bytes_per_node(self.metadata.record_size)? * self.metadata.node_count as u64;
+        if search_tree_size + 16 > file_size {
+            return Err(MmdbError::InvalidData(
+                "search tree extends past end of file",
+            ));
+        }
+
+        let mut separator = [0u8; 16];
+        self.reader.seek(SeekFrom::Start(search_tree_size))?;
+        self.reader.read_exact(&mut separator)?;
+        if separator != [0u8; 16] {
+            return Err(MmdbError::InvalidData("missing data section separator"));
+        }
+
+        // Walk both edges of the address space to make sure the tree can be traversed
+        let num_bits = match self.metadata.ip_version {
+            4 => 32,
+            _ => 128,
+        };
+        self.query_ip_uint(0, num_bits)?;
+        self.query_ip_uint(u128::MAX >> (128 - num_bits), num_bits)?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    /// When the database was built, from its metadata
+    pub fn build_time(&self) -> Option<SystemTime> {
+        let epoch = self.metadata.build_epoch?;
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(epoch))
+    }
+
+    /// The address families this database can answer for, used when resolving domains
+    pub fn address_family(&self) -> dns::AddressFamily {
+        match self.metadata.ip_version {
+            4 => dns::AddressFamily::Ipv4,
+            _ => dns::AddressFamily::Both,
+        }
+    }
+
+    /// Sets the DNS servers used to resolve domains, instead of the ones in `/etc/resolv.conf`
+    pub fn set_resolver_config(&mut self, config: dns::ResolverConfig) {
+        self.set_resolver(Arc::new(config));
+    }
+
+    /// Resolves domains through `resolver` rather than the built-in DNS client
+    pub fn set_resolver(&mut self, resolver: Arc<dyn dns::Resolver>) {
+        self.resolver = Some(resolver);
+    }
+
+    pub fn resolver(&self) -> Arc<dyn dns::Resolver> {
+        self.resolver
+            .clone()
+            .unwrap_or_else(|| Arc::new(dns::ResolverConfig::system()))
     }
 
     pub fn query_string(&mut self, ip_or_domain: &str) -> Result<MmdbInfo, MmdbError> {
@@ -180,6 +506,7 @@ impl<T: Read + Seek> Mmdb<T> {
                 Ok(MmdbInfo {
                     data,
                     dns_info: None,
+                    reverse_name: None,
                 })
             }
             Err(_) => {
@@ -187,29 +514,57 @@ impl<T: Read + Seek> Mmdb<T> {
                     let _ = tx.send(QueryProgress::Started);
                 }
 
-                let ip = dns::query_dns_for_domain(ip_or_domain)?;
+                let ips = self
+                    .resolver()
+                    .resolve(ip_or_domain, self.address_family())?
+                    .into_iter()
+                    .map(|record| record.ip)
+                    .collect::<Vec<_>>();
 
                 if let Some(tx) = progress_tx {
                     let _ = tx.send(QueryProgress::DnsResolved);
                 }
 
-                let data = self.query_ip(ip)?;
+                let data = self.query_ips(&ips)?;
 
                 if let Some(tx) = progress_tx {
                     let _ = tx.send(QueryProgress::Completed);
                 }
 
-                Ok(MmdbInfo {
-                    data,
-                    dns_info: Some(DnsInfo {
-                        domain: ip_or_domain.to_string(),
-                        resolved_ip: ip,
-                    }),
-                })
+                Ok(MmdbInfo::for_domain(ip_or_domain, ips, data))
             }
         }
     }
 
+    /// Looks up the hostname of `ip` with the configured resolver, returning `None` if it has no
+    /// PTR record.
+    pub fn reverse_lookup(&self, ip: IpAddr) -> Result<Option<String>, MmdbError> {
+        Ok(self.resolver().reverse_lookup(ip)?)
+    }
+
+    /// Looks up the MX, NS, TXT and SOA records of `domain`, and the database record of every
+    /// address of its mail and name servers.
+    pub fn query_domain_records(&mut self, domain: &str) -> Result<RecordsInfo, MmdbError> {
+        let resolver = self.resolver();
+        let mut records = DomainRecords::default();
+        for record_type in DOMAIN_RECORD_TYPES {
+            records.add(resolver.lookup(domain, record_type))?;
+        }
+
+        let mut info = records.finish(domain)?;
+        for host in &mut info.hosts {
+            // A server that fails to resolve is still worth listing, just without addresses
+            let ips = resolver
+                .resolve(&host.name, self.address_family())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|record| record.ip)
+                .collect();
+            host.addresses = self.query_addresses(ips)?;
+        }
+        Ok(info)
+    }
+
     pub fn query_ip(&mut self, ip: impl Into<IpAddr>) -> Result<Option<Type>, MmdbError> {
         let ip = ip.into();
         if is_verbose() {
@@ -253,6 +608,89 @@ impl<T: Read + Seek> Mmdb<T> {
         }
     }
 
+    /// Looks up many addresses at once, returning results in the same order as `ips`.
+    ///
+    /// Addresses are walked in sorted order so that the tree nodes shared by neighbouring
+    /// addresses are only read once, and records that several addresses point at are only
+    /// decoded once.
+    pub fn query_ips(&mut self, ips: &[IpAddr]) -> Result<Vec<Option<Type>>, MmdbError> {
+        let mut keys = Vec::with_capacity(ips.len());
+        for ip in ips {
+            let key = match (self.metadata.ip_version, ip) {
+                (4, IpAddr::V4(ip)) => (ip.to_bits() as u128, 32),
+                (4, IpAddr::V6(_)) => return Err(MmdbError::WrongDatabaseType),
+                (6, IpAddr::V4(ip)) => (ip.to_ipv6_compatible().to_bits(), 128),
+                (6, IpAddr::V6(ip)) => (ip.to_bits(), 128),
+                _ => {
+                    return Err(MmdbError::InvalidMetadata(
+                        "database has invalid ip version",
+                    ));
+                }
+            };
+            keys.push(key);
+        }
+
+        let mut order = (0..ips.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| keys[i].0);
+
+        let mut results = vec![None; ips.len()];
+        let mut decoded: HashMap<usize, Type> = HashMap::new();
+        // path[d] is the file offset of the node visited at depth d by the previous address
+        let mut path: Vec<u64> = vec![0];
+        let mut previous: Option<u128> = None;
+
+        for i in order {
+            let (ip, num_bits) = keys[i];
+
+            let shared_bits = match previous {
+                Some(previous) => {
+                    ((previous ^ ip).leading_zeros() as usize).saturating_sub(128 - num_bits)
+                }
+                None => 0,
+            };
+            previous = Some(ip);
+
+            let mut depth = shared_bits.min(path.len() - 1);
+            path.truncate(depth + 1);
+
+            let record = loop {
+                if depth >= num_bits {
+                    break RecordReadResult::NoData;
+                }
+                let bit = (ip >> (num_bits - 1 - depth)) & 1 == 1;
+                self.reader.seek(SeekFrom::Start(path[depth]))?;
+                match read_record(&mut self.reader, &self.metadata, bit)? {
+                    RecordReadResult::TraverseTreeTo(pos) => {
+                        path.push(pos as u64);
+                        depth += 1;
+                    }
+                    record => break record,
+                }
+            };
+
+            if let RecordReadResult::Data(pos) = record {
+                let typ = match decoded.get(&pos) {
+                    Some(typ) => typ.clone(),
+                    None => {
+                        self.reader.seek(SeekFrom::Start(pos as u64))?;
+                        let typ = read_type(&mut self.reader, Some(&self.metadata))?;
+                        decoded.insert(pos, typ.clone());
+                        typ
+                    }
+                };
+                results[i] = Some(typ);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Looks up `ips` with [`Mmdb::query_ips`], pairing each address with its record
+    fn query_addresses(&mut self, ips: Vec<IpAddr>) -> Result<Vec<AddressInfo>, MmdbError> {
+        let data = self.query_ips(&ips)?;
+        Ok(AddressInfo::zip(ips, data))
+    }
+
     pub fn query_ip_uint(&mut self, ip: u128, num_bits: usize) -> Result<Option<Type>, MmdbError> {
         self.reader.seek(SeekFrom::Start(0))?;
         if is_verbose() {
@@ -365,6 +803,7 @@ struct MmdbMetadata {
     node_count: u32,
     record_size: u16,
     ip_version: u16,
+    build_epoch: Option<u64>,
 }
 
 impl MmdbMetadata {
@@ -386,16 +825,24 @@ impl MmdbMetadata {
             return Err(MmdbError::InvalidMetadata("does not contain ip version"));
         };
         let ip_version = *ip_version;
+        // Optional as far as this reader is concerned, only used to tell how old the data is
+        let build_epoch = match map.get("build_epoch") {
+            Some(Type::U64(epoch)) => Some(*epoch),
+            Some(Type::U32(epoch)) => Some(*epoch as u64),
+            Some(Type::U16(epoch)) => Some(*epoch as u64),
+            _ => None,
+        };
 
         Ok(Self {
             node_count,
             record_size,
             ip_version,
+            build_epoch,
         })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[allow(unused)]
 pub enum Type {
     Utf8String(String),
@@ -414,6 +861,32 @@ pub enum Type {
     Float(f32),
 }
 
+impl Type {
+    /// One line description of a record, built from the well known fields of ip-to-asn and
+    /// ip-to-country databases, falling back to the full record for anything else.
+    pub fn short_description(&self) -> String {
+        let Type::Map(map) = self else {
+            return self.to_string().replace('\n', " ");
+        };
+
+        let mut parts = vec![];
+        if let Some(asn) = map.get("asn") {
+            parts.push(format!("AS{asn}"));
+        }
+        for key in ["name", "org", "country_code"] {
+            if let Some(value) = map.get(key) {
+                parts.push(value.to_string());
+            }
+        }
+
+        if parts.is_empty() {
+            self.to_string().replace('\n', " ")
+        } else {
+            parts.join(" / ")
+        }
+    }
+}
+
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         pub fn pretty_print_type(
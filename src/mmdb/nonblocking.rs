@@ -0,0 +1,116 @@
+use std::{io::Cursor, net::IpAddr, path::Path, sync::Arc};
+
+use super::{
+    DOMAIN_RECORD_TYPES, DomainRecords, Mmdb, MmdbError, MmdbInfo, RecordsInfo, Type, dns,
+};
+
+/// A cheaply cloneable lookup handle for use from async tasks.
+///
+/// The whole database is held in memory, so lookups never touch the disk and can run directly
+/// on the runtime. Only domain resolution awaits, and it goes over a tokio socket.
+#[derive(Clone)]
+pub struct AsyncMmdb {
+    mmdb: Mmdb<Cursor<Arc<[u8]>>>,
+}
+
+impl AsyncMmdb {
+    pub async fn open(path: impl AsRef<Path>) -> Result<AsyncMmdb, MmdbError> {
+        let bytes = tokio::fs::read(path).await?;
+        Self::from_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: impl Into<Arc<[u8]>>) -> Result<AsyncMmdb, MmdbError> {
+        let mmdb = Mmdb::new(Cursor::new(bytes.into()))?;
+        Ok(Self { mmdb })
+    }
+
+    pub fn set_resolver_config(&mut self, config: dns::ResolverConfig) {
+        self.mmdb.set_resolver_config(config);
+    }
+
+    /// Resolves domains through `resolver`. Resolvers other than the built-in one are called
+    /// on tokio's blocking thread pool.
+    pub fn set_resolver(&mut self, resolver: Arc<dyn dns::Resolver>) {
+        self.mmdb.set_resolver(resolver);
+    }
+
+    pub fn query_ip(&self, ip: impl Into<IpAddr>) -> Result<Option<Type>, MmdbError> {
+        // Each lookup gets its own cursor over the shared buffer, so handles never contend
+        self.mmdb.clone().query_ip(ip)
+    }
+
+    pub fn query_ips(&self, ips: &[IpAddr]) -> Result<Vec<Option<Type>>, MmdbError> {
+        self.mmdb.clone().query_ips(ips)
+    }
+
+    pub async fn reverse_lookup(&self, ip: IpAddr) -> Result<Option<String>, MmdbError> {
+        let resolver = self.mmdb.resolver();
+        if let Some(config) = resolver.config() {
+            return Ok(dns::reverse_lookup_async(config, ip).await?);
+        }
+        run_blocking(move || resolver.reverse_lookup(ip)).await
+    }
+
+    pub async fn query_string(&self, ip_or_domain: &str) -> Result<MmdbInfo, MmdbError> {
+        match ip_or_domain.parse::<IpAddr>() {
+            Ok(ip) => Ok(MmdbInfo {
+                data: self.query_ip(ip)?,
+                dns_info: None,
+                reverse_name: None,
+            }),
+            Err(_) => {
+                let ips = self.resolve(ip_or_domain).await?;
+                let data = self.query_ips(&ips)?;
+                Ok(MmdbInfo::for_domain(ip_or_domain, ips, data))
+            }
+        }
+    }
+
+    /// Same as [`Mmdb::query_domain_records`]
+    pub async fn query_domain_records(&self, domain: &str) -> Result<RecordsInfo, MmdbError> {
+        let resolver = self.mmdb.resolver();
+        let Some(config) = resolver.config() else {
+            let mut mmdb = self.mmdb.clone();
+            let domain = domain.to_string();
+            return run_blocking(move || mmdb.query_domain_records(&domain)).await;
+        };
+
+        let mut records = DomainRecords::default();
+        for record_type in DOMAIN_RECORD_TYPES {
+            records.add(dns::lookup_async(config, domain, record_type).await)?;
+        }
+
+        let mut info = records.finish(domain)?;
+        for host in &mut info.hosts {
+            // A server that fails to resolve is still worth listing, just without addresses
+            let ips = self.resolve(&host.name).await.unwrap_or_default();
+            host.addresses = self.mmdb.clone().query_addresses(ips)?;
+        }
+        Ok(info)
+    }
+
+    async fn resolve(&self, domain: &str) -> Result<Vec<IpAddr>, MmdbError> {
+        let resolver = self.mmdb.resolver();
+        let family = self.mmdb.address_family();
+        let records = match resolver.config() {
+            Some(config) => dns::resolve_async(config, domain, family).await?,
+            None => {
+                let domain = domain.to_string();
+                run_blocking(move || resolver.resolve(&domain, family)).await?
+            }
+        };
+        Ok(records.into_iter().map(|record| record.ip).collect())
+    }
+}
+
+async fn run_blocking<T: Send + 'static, E: Send + 'static>(
+    f: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> Result<T, MmdbError>
+where
+    MmdbError: From<E>,
+{
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| MmdbError::BadIo(std::io::Error::other(err)))?;
+    Ok(result?)
+}
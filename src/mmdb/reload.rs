@@ -0,0 +1,136 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock, Weak,
+        mpsc::{Receiver, Sender},
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use super::{Mmdb, MmdbError, archive, dns};
+
+pub type InMemoryMmdb = Mmdb<Cursor<Arc<[u8]>>>;
+
+#[derive(Debug)]
+pub enum ReloadEvent {
+    /// A new version of the database was opened, validated and swapped in
+    Reloaded,
+    /// The file changed but could not be opened or validated, the previous database is still in use
+    Rejected(MmdbError),
+}
+
+/// A database handle that can swap in a new version of its file without interrupting readers.
+///
+/// Each version is read fully into memory (extracting it if the file is an archive), so it
+/// stays intact however the file is replaced on disk. Readers take the current database with
+/// [`ReloadableMmdb::current`] and keep using it for as long as they hold it, even if a reload
+/// happens in the meantime.
+pub struct ReloadableMmdb {
+    path: PathBuf,
+    current: RwLock<InMemoryMmdb>,
+    last_seen: Mutex<Option<FileSignature>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct FileSignature {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileSignature {
+    fn of(path: &Path) -> Option<FileSignature> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(FileSignature {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+impl ReloadableMmdb {
+    pub fn open(path: impl Into<PathBuf>) -> Result<ReloadableMmdb, MmdbError> {
+        let path = path.into();
+        let last_seen = FileSignature::of(&path);
+        let mmdb = open_validated(&path)?;
+        Ok(Self {
+            path,
+            current: RwLock::new(mmdb),
+            last_seen: Mutex::new(last_seen),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn current(&self) -> InMemoryMmdb {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Sets the DNS servers used by this and every future version of the database
+    pub fn set_resolver_config(&self, config: dns::ResolverConfig) {
+        self.current.write().unwrap().set_resolver_config(config);
+    }
+
+    /// Resolves domains through `resolver` in this and every future version of the database
+    pub fn set_resolver(&self, resolver: Arc<dyn dns::Resolver>) {
+        self.current.write().unwrap().set_resolver(resolver);
+    }
+
+    /// Re-opens the file unconditionally. On failure the previous database is kept.
+    pub fn reload(&self) -> Result<(), MmdbError> {
+        *self.last_seen.lock().unwrap() = FileSignature::of(&self.path);
+        let mut mmdb = open_validated(&self.path)?;
+        let mut current = self.current.write().unwrap();
+        mmdb.resolver = current.resolver.take();
+        *current = mmdb;
+        Ok(())
+    }
+
+    /// Re-opens the file if its size or modification time changed since it was last seen.
+    ///
+    /// Returns `Ok(true)` if a new database was swapped in.
+    pub fn reload_if_changed(&self) -> Result<bool, MmdbError> {
+        let signature = FileSignature::of(&self.path);
+        if signature.is_none() || *self.last_seen.lock().unwrap() == signature {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// Polls the file on a background thread, reporting every reload attempt on the returned
+    /// channel. The thread exits once the last handle to the database is dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> (Receiver<ReloadEvent>, JoinHandle<()>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let weak = Arc::downgrade(self);
+        let handle = std::thread::spawn(move || watch_loop(weak, interval, tx));
+        (rx, handle)
+    }
+}
+
+fn watch_loop(weak: Weak<ReloadableMmdb>, interval: Duration, tx: Sender<ReloadEvent>) {
+    loop {
+        std::thread::sleep(interval);
+        let Some(mmdb) = weak.upgrade() else {
+            return;
+        };
+        let event = match mmdb.reload_if_changed() {
+            Ok(false) => continue,
+            Ok(true) => ReloadEvent::Reloaded,
+            Err(err) => ReloadEvent::Rejected(err),
+        };
+        if tx.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn open_validated(path: &Path) -> Result<InMemoryMmdb, MmdbError> {
+    let bytes: Arc<[u8]> = archive::read_database(path)?.into();
+    let mut mmdb = Mmdb::new(Cursor::new(bytes))?;
+    mmdb.validate()?;
+    Ok(mmdb)
+}